@@ -0,0 +1,35 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nalgebra::Vector2;
+
+use orbit_fit_100_loc::*;
+
+fn truth() -> State {
+    State {
+        pos: Vector2::new(3.0, -8.0),
+        vel: Vector2::new(0.25, 0.5),
+    }
+}
+
+fn bench_fit(c: &mut Criterion) {
+    let config = IntegratorConfig::default();
+    let observations = synthesize_observations(&truth(), 0.01, 0, &config);
+    c.bench_function("fit_trajectory", |b| b.iter(|| fit_trajectory(black_box(&observations)).unwrap()));
+}
+
+fn bench_integrate(c: &mut Criterion) {
+    let config = IntegratorConfig::default();
+    let initial = truth();
+    c.bench_function("integrate_trajectory_euler", |b| {
+        b.iter(|| integrate_trajectory_euler(black_box(&initial), &config).last())
+    });
+}
+
+fn bench_jacobian(c: &mut Criterion) {
+    let config = IntegratorConfig::default();
+    let observations = synthesize_observations(&truth(), 0.01, 0, &config);
+    let initial = truth();
+    c.bench_function("jacobian", |b| b.iter(|| jacobian(black_box(&initial), &observations, &config)));
+}
+
+criterion_group!(benches, bench_fit, bench_integrate, bench_jacobian);
+criterion_main!(benches);
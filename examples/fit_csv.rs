@@ -0,0 +1,39 @@
+//! Fits bearing observations read from a file, see [`read_observations_csv`] for the format.
+//!
+//! ```text
+//! cargo run --example fit_csv -- observations.csv [residuals.svg]
+//! ```
+
+use std::error::Error;
+use std::process::ExitCode;
+
+use orbit_fit_100_loc::*;
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().ok_or("usage: fit_csv <observations.csv> [residuals.svg]")?;
+    let plot_path = args.next().unwrap_or_else(|| "residuals.svg".to_string());
+
+    let observed = read_observations_csv(&path)?;
+    // one model sample per observation, on the default time grid
+    let mut config = FitConfig::default();
+    config.integration.steps = observed.len() * config.integration.stride;
+    let (computed, report) = fit_trajectory_with_config(&observed, &config)?;
+    println!("report: {:?}", report);
+    println!("computed state: {}", computed.display(&config.integration));
+    println!("residual rms: {}", residual_rms(&computed, &observed, &config.integration));
+
+    plot_residuals(&plot_path, &computed, &observed, &config.integration)?;
+    println!("residuals written to {}", plot_path);
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use super::*;
+
+/// Chainable configuration of a bearing fit, see [`FitBuilder::build`].
+///
+/// The defaults reproduce [`fit_trajectory`].
+#[derive(Debug, Clone, Default)]
+pub struct FitBuilder {
+    config: FitConfig,
+    sigmas: Option<Vec<f64>>,
+    initial_guess: Option<State<f64>>,
+}
+
+impl FitBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn dt(mut self, dt: f64) -> Self {
+        self.config.integration.dt = dt;
+        self
+    }
+
+    pub fn steps(mut self, steps: usize) -> Self {
+        self.config.integration.steps = steps;
+        self
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        self.config.integration.stride = stride;
+        self
+    }
+
+    pub fn integrator(mut self, integrator: Integrator) -> Self {
+        self.config.integration.integrator = integrator;
+        self
+    }
+
+    pub fn mu(mut self, mu: f64) -> Self {
+        self.config.integration.mu = mu;
+        self
+    }
+
+    pub fn epsilon(mut self, epsilon: f64) -> Self {
+        self.config.integration.epsilon = epsilon;
+        self
+    }
+
+    pub fn drag(mut self, drag: f64) -> Self {
+        self.config.integration.drag = drag;
+        self
+    }
+
+    pub fn bodies(mut self, bodies: Vec<Body>) -> Self {
+        self.config.integration.bodies = bodies;
+        self
+    }
+
+    pub fn epoch_offset(mut self, epoch_offset: f64) -> Self {
+        self.config.integration.epoch_offset = epoch_offset;
+        self
+    }
+
+    /// Local error tolerance of [`Integrator::Rkf45`].
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        self.config.integration.tolerance = tolerance;
+        self
+    }
+
+    pub fn center(mut self, center: Vector2<f64>) -> Self {
+        self.config.integration.center = center;
+        self
+    }
+
+    pub fn omega(mut self, omega: f64) -> Self {
+        self.config.integration.omega = omega;
+        self
+    }
+
+    /// Weights the residual of observation `i` by `1 / sigmas[i]`, see [`fit_trajectory_weighted`].
+    pub fn sigmas(mut self, sigmas: Vec<f64>) -> Self {
+        self.sigmas = Some(sigmas);
+        self
+    }
+
+    /// Starts the solver from `initial_guess` instead of the unit circle heuristic.
+    pub fn initial_guess(mut self, initial_guess: State<f64>) -> Self {
+        self.initial_guess = Some(initial_guess);
+        self
+    }
+
+    pub fn ftol(mut self, ftol: f64) -> Self {
+        self.config.ftol = ftol;
+        self
+    }
+
+    pub fn xtol(mut self, xtol: f64) -> Self {
+        self.config.xtol = xtol;
+        self
+    }
+
+    pub fn gtol(mut self, gtol: f64) -> Self {
+        self.config.gtol = gtol;
+        self
+    }
+
+    pub fn initial_step(mut self, initial_step: f64) -> Self {
+        self.config.initial_step = initial_step;
+        self
+    }
+
+    pub fn patience(mut self, patience: usize) -> Self {
+        self.config.patience = patience;
+        self
+    }
+
+    pub fn free_parameters(mut self, free_parameters: [bool; 4]) -> Self {
+        self.config.free_parameters = free_parameters;
+        self
+    }
+
+    pub fn bounds(mut self, bounds: [(f64, f64); 4]) -> Self {
+        self.config.bounds = bounds;
+        self
+    }
+
+    pub fn jacobian(mut self, jacobian: JacobianMode) -> Self {
+        self.config.jacobian = jacobian;
+        self
+    }
+
+    pub fn build(self) -> Fitter {
+        Fitter {
+            config: self.config,
+            sigmas: self.sigmas,
+            initial_guess: self.initial_guess,
+        }
+    }
+}
+
+/// A configured bearing fit, built by [`FitBuilder`].
+#[derive(Debug, Clone)]
+pub struct Fitter {
+    config: FitConfig,
+    sigmas: Option<Vec<f64>>,
+    initial_guess: Option<State<f64>>,
+}
+
+impl Fitter {
+    pub fn config(&self) -> &FitConfig {
+        &self.config
+    }
+
+    pub fn fit(&self, observations: &[f64]) -> Result<(State<f64>, MinimizationReport<f64>), FitError> {
+        fit(observations, self.sigmas.as_deref(), self.initial_guess.clone(), &Bearing, &self.config)
+    }
+}
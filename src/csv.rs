@@ -0,0 +1,53 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use super::*;
+
+/// Writes the integrated trajectory as `t,x,y,vx,vy` rows, preceded by a header row.
+///
+/// Row `i` is the state after `i + 1` integration steps, i.e. at `t = epoch_offset + (i + 1) * dt`.
+pub fn write_trajectory_csv<W: Write>(mut w: W, initial: &State<f64>, config: &IntegratorConfig) -> io::Result<()> {
+    writeln!(w, "t,x,y,vx,vy")?;
+    for (i, state) in integrate_trajectory(initial, config).enumerate() {
+        let t = config.epoch_offset + (i + 1) as f64 * config.dt;
+        writeln!(w, "{},{},{},{},{}", t, state.pos[0], state.pos[1], state.vel[0], state.vel[1])?;
+    }
+    Ok(())
+}
+
+/// Reads bearing observations (radians), one per line, skipping blank lines and `#` comments.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] on malformed lines or if fewer than two
+/// observations are found, since the fit needs two of them for its initial guess.
+pub fn read_observations_csv<P: AsRef<Path>>(path: P) -> io::Result<Vec<f64>> {
+    let path = path.as_ref();
+    let mut observations = Vec::new();
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let angle = line.parse::<f64>().map_err(|e| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}:{}: invalid observation {:?}: {}", path.display(), i + 1, line, e),
+        ))?;
+        observations.push(angle);
+    }
+    if observations.len() < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: expected at least 2 observations, found {}", path.display(), observations.len()),
+        ));
+    }
+    Ok(observations)
+}
+
+/// Reads bearing observations with [`read_observations_csv`] and fits them with
+/// [`fit_trajectory_checked`], failing if the solver does not converge.
+pub fn fit_observations_csv<P: AsRef<Path>>(path: P, config: &FitConfig) -> Result<(State<f64>, Vec<f64>), Error> {
+    let observations = read_observations_csv(path)?;
+    let state = fit_trajectory_checked(&observations, config)?;
+    Ok((state, observations))
+}
@@ -0,0 +1,132 @@
+use super::*;
+
+/// Equations of motion: the acceleration of the object in a given state.
+///
+/// Implementations generic over `T` work with the automatic differentiation of the fit, see
+/// [`fit_trajectory_with_dynamics`].
+pub trait Dynamics<T> {
+    fn acceleration(&self, state: &State<T>) -> Vector2<T>;
+
+    /// Trajectory from `initial_state`, see [`integrate_trajectory_with`].
+    fn trajectory(&self, initial_state: &State<T>, config: &IntegratorConfig) -> Vec<State<T>>
+    where
+        T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+        Self: Sized,
+    {
+        integrate_trajectory_with(initial_state, self, config).collect()
+    }
+
+    /// States at arbitrary `times`, see [`states_at`].
+    fn states_at(&self, initial_state: &State<T>, times: &[f64], config: &IntegratorConfig) -> Vec<State<T>>
+    where
+        T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+        Self: Sized,
+    {
+        states_with(initial_state, times, config, &|pos, vel| self.acceleration(&State { pos: *pos, vel: *vel }))
+    }
+}
+
+/// The force model of the configuration itself (`mu`, `epsilon`, `bodies`, `drag`, `center`
+/// and `omega`), as used by the fit by default.
+///
+/// [`Dynamics::trajectory`] and [`Dynamics::states_at`] propagate this force model on the grid
+/// and with the integrator of the `config` argument, through [`integrate_trajectory`] and
+/// [`states_at`] so that [`Integrator::Kepler`] still propagates analytically.
+impl<T> Dynamics<T> for IntegratorConfig
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    fn acceleration(&self, state: &State<T>) -> Vector2<T> {
+        total_acceleration(self)(&state.pos, &state.vel)
+    }
+
+    fn trajectory(&self, initial_state: &State<T>, config: &IntegratorConfig) -> Vec<State<T>>
+    where
+        T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+        Self: Sized,
+    {
+        integrate_trajectory(initial_state, &self.on_grid_of(config)).collect()
+    }
+
+    fn states_at(&self, initial_state: &State<T>, times: &[f64], config: &IntegratorConfig) -> Vec<State<T>>
+    where
+        T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+        Self: Sized,
+    {
+        states_at(initial_state, times, &self.on_grid_of(config))
+    }
+}
+
+impl IntegratorConfig {
+    /// The force model of `self` with the integrator and time grid of `config`.
+    fn on_grid_of(&self, config: &IntegratorConfig) -> IntegratorConfig {
+        IntegratorConfig {
+            integrator: config.integrator,
+            dt: config.dt,
+            steps: config.steps,
+            stride: config.stride,
+            epoch_offset: config.epoch_offset,
+            tolerance: config.tolerance,
+            ..self.clone()
+        }
+    }
+}
+
+/// A point mass at `center`, the force model of the default [`IntegratorConfig`].
+#[derive(Debug, Clone, Copy)]
+pub struct TwoBody {
+    /// Gravitational parameter.
+    pub mu: f64,
+    /// Softening length, see [`IntegratorConfig::epsilon`].
+    pub epsilon: f64,
+    /// Position of the body, see [`IntegratorConfig::center`].
+    pub center: Vector2<f64>,
+}
+
+impl Default for TwoBody {
+    fn default() -> Self {
+        Self { mu: 1.0, epsilon: 0.0, center: Vector2::zeros() }
+    }
+}
+
+/// The central body of the configuration, without the other bodies, drag or frame rotation.
+impl From<&IntegratorConfig> for TwoBody {
+    fn from(config: &IntegratorConfig) -> Self {
+        Self { mu: config.mu, epsilon: config.epsilon, center: config.center }
+    }
+}
+
+impl<T> Dynamics<T> for TwoBody
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    fn acceleration(&self, state: &State<T>) -> Vector2<T> {
+        let relative = translate(state, &-self.center);
+        acceleration(&relative.pos, T::from(self.mu).unwrap(), T::from(self.epsilon).unwrap())
+    }
+}
+
+/// Same as [`integrate_trajectory`], with the force model given by `dynamics` instead of the
+/// configuration (`mu`, `epsilon`, `bodies`, `drag`, `center`, `omega`).
+///
+/// [`Integrator::Kepler`] is specific to the two-body problem and falls back to RK4.
+pub fn integrate_trajectory_with<'a, T, D>(initial_state: &State<T>, dynamics: &'a D, config: &IntegratorConfig) -> impl Iterator<Item = State<T>> + 'a
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    D: Dynamics<T>,
+{
+    let acc = move |pos: &Vector2<T>, vel: &Vector2<T>| dynamics.acceleration(&State { pos: *pos, vel: *vel });
+    let start;
+    let initial_state = if config.epoch_offset != 0.0 {
+        start = states_with(initial_state, &[config.epoch_offset], config, &acc).remove(0);
+        &start
+    } else {
+        initial_state
+    };
+    match config.integrator {
+        Integrator::Euler => Box::new(integrate_with_step(initial_state, config, acc, euler_step)) as Box<dyn Iterator<Item = State<T>> + 'a>,
+        Integrator::Rk4 | Integrator::Kepler => Box::new(integrate_with_step(initial_state, config, acc, rk4_step)),
+        Integrator::Rkf45 => Box::new(rkf45_trajectory(initial_state, config, &acc).into_iter()),
+        Integrator::SymplecticEuler => Box::new(integrate_with_step(initial_state, config, acc, symplectic_euler_step)),
+    }
+}
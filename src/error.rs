@@ -0,0 +1,98 @@
+#[cfg(feature = "std")]
+use std::io;
+
+use super::*;
+
+/// Crate-level error, collecting the more specific ones ([`FitError`], [`FitFailure`],
+/// [`ParseStateError`], I/O and plotting errors) under a single matchable type.
+///
+/// The I/O and plotting variants need the `std` feature.
+#[derive(Debug)]
+pub enum Error {
+    /// At least two observations are needed, only this many were given.
+    TooFewObservations(usize),
+    /// A matrix that had to be inverted (e.g. `JᵀJ` for the covariance) is singular.
+    Singular,
+    /// The solver stopped without converging, for this reason.
+    NotConverged(TerminationReason),
+    /// The sample at this index is at the origin, where the bearing is undefined.
+    UndefinedBearing(usize),
+    /// Any other invalid input to the fit.
+    Fit(FitError),
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    Parse(ParseStateError),
+    #[cfg(feature = "std")]
+    Plot(Box<dyn std::error::Error>),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::TooFewObservations(n) => write!(f, "at least 2 observations are needed, got {}", n),
+            Error::Singular => write!(f, "singular matrix"),
+            Error::NotConverged(reason) => write!(f, "the fit did not converge: {:?}", reason),
+            Error::UndefinedBearing(i) => write!(f, "sample {} is at the origin, its bearing is undefined", i),
+            Error::Fit(e) => write!(f, "{}", e),
+            #[cfg(feature = "std")]
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Parse(e) => write!(f, "{}", e),
+            #[cfg(feature = "std")]
+            Error::Plot(e) => write!(f, "plotting failed: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Fit(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Parse(e) => Some(e),
+            Error::Plot(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<FitError> for Error {
+    fn from(e: FitError) -> Self {
+        match e {
+            FitError::TooFewObservations(n) => Error::TooFewObservations(n),
+            FitError::SingularCovariance => Error::Singular,
+            e => Error::Fit(e),
+        }
+    }
+}
+
+impl From<FitFailure> for Error {
+    fn from(e: FitFailure) -> Self {
+        match e {
+            FitFailure::Invalid(e) => e.into(),
+            FitFailure::LostPatience => Error::NotConverged(TerminationReason::LostPatience),
+            FitFailure::Numerical(reason) => Error::NotConverged(TerminationReason::Numerical(reason)),
+            FitFailure::Solver(reason) => Error::NotConverged(reason),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<ParseStateError> for Error {
+    fn from(e: ParseStateError) -> Self {
+        Error::Parse(e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Box<dyn std::error::Error>> for Error {
+    fn from(e: Box<dyn std::error::Error>) -> Self {
+        Error::Plot(e)
+    }
+}
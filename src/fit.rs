@@ -1,116 +1,1480 @@
+use core::cell::{OnceCell, RefCell};
+
 use super::*;
 
-const DT: f64 = 0.25;
+/// Default [`IntegratorConfig::dt`].
+pub const DEFAULT_DT: f64 = 0.25;
+/// Default [`IntegratorConfig::steps`].
+pub const DEFAULT_STEPS: usize = 120;
+/// Default [`IntegratorConfig::stride`]; the model has `DEFAULT_STEPS / DEFAULT_STRIDE = 24`
+/// samples, see [`IntegratorConfig::num_samples`].
+pub const DEFAULT_STRIDE: usize = 5;
+/// Replaces non-finite residuals (e.g. a trial trajectory through the origin), so that the
+/// solver sees a huge cost and rejects the step instead of propagating NaNs.
+const NON_FINITE_PENALTY: f64 = 1e6;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IntegratorConfig {
+    /// Integration time step, shared by the forward model and the fit; negative to integrate
+    /// backward in time, e.g. when the fitted state is at the end of the arc.
+    pub dt: f64,
+    /// Number of integration steps produced by the integrator.
+    ///
+    /// `sampled_trajectory` keeps every `stride`-th step starting from the first one, so the
+    /// model yields [`IntegratorConfig::num_samples`] samples, and the fit expects exactly one
+    /// observation per sample.
+    pub steps: usize,
+    /// Number of integration steps between two observations; `0` is treated as `1`.
+    pub stride: usize,
+    /// Integrator used by the forward model.
+    pub integrator: Integrator,
+    /// Gravitational parameter of the central body.
+    pub mu: f64,
+    /// Softening length: the acceleration denominator becomes `(r^2 + epsilon^2)^(3/2)`.
+    pub epsilon: f64,
+    /// Additional fixed point masses besides the central body of `mu` at `center` (ignored by
+    /// [`Integrator::Kepler`] and in 3D).
+    pub bodies: Vec<Body>,
+    /// Drag coefficient `k` of the `-k * |v| * v` acceleration term, `0` to disable drag.
+    pub drag: f64,
+    /// J2 zonal harmonic of the central body, only used in 3D, see [`j2_acceleration`].
+    pub j2: f64,
+    /// Equatorial radius of the central body, the reference radius of [`IntegratorConfig::j2`].
+    pub equatorial_radius: f64,
+    /// Time, of either sign, from the epoch of the state to the start of the observations: the
+    /// samples are taken at `epoch_offset + (i + 1) * dt`, and observation times (see
+    /// [`ObservationModel::observation_times`]) are measured from `epoch_offset`.
+    pub epoch_offset: f64,
+    /// Local error tolerance of [`Integrator::Rkf45`], ignored by the other integrators.
+    pub tolerance: f64,
+    /// Position of the central body (2D only): its acceleration uses `pos - center`, and the
+    /// observation models (and initial guesses) see positions relative to it, e.g. bearings
+    /// are measured from it.
+    pub center: Vector2<f64>,
+    /// Angular rate (counter-clockwise) of the reference frame about `center` (2D only, ignored
+    /// by [`Integrator::Kepler`]), adding the centrifugal `omega^2 * (pos - center)` and
+    /// Coriolis `-2 * omega ẑ × vel` accelerations; `0` for an inertial frame.
+    pub omega: f64,
+}
 
-pub fn fit_trajectory(observations: &Vec<f64>) -> (State<f64>, MinimizationReport<f64>) {
-    let guess_position = |angle: f64| Vector2::new(angle.cos(), angle.sin());
-    let initial_guess = State {
-        pos: guess_position(observations[0]),
-        vel: (guess_position(observations[1]) - guess_position(observations[0])) / DT,
+/// A fixed point mass attracting the object.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Body {
+    pub pos: Vector2<f64>,
+    /// Gravitational parameter.
+    pub mu: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Integrator {
+    #[default]
+    Euler,
+    Rk4,
+    /// Analytic two-body propagation, see [`propagate_kepler`] (ignores the softening length and drag).
+    Kepler,
+    /// Adaptive step size, see [`integrate_trajectory_rkf45`].
+    Rkf45,
+    /// Semi-implicit Euler, see [`integrate_trajectory_symplectic_euler`].
+    SymplecticEuler,
+}
+
+impl IntegratorConfig {
+    /// Number of model samples (and residuals), i.e. `ceil(steps / stride)`.
+    pub fn num_samples(&self) -> usize {
+        self.steps.div_ceil(self.stride.max(1))
+    }
+}
+
+impl Default for IntegratorConfig {
+    fn default() -> Self {
+        Self { dt: DEFAULT_DT, steps: DEFAULT_STEPS, stride: DEFAULT_STRIDE, integrator: Integrator::default(), mu: 1.0, epsilon: 0.0, bodies: Vec::new(), drag: 0.0, j2: 0.0, equatorial_radius: 1.0, epoch_offset: 0.0, tolerance: 1e-9, center: Vector2::zeros(), omega: 0.0 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FitError {
+    /// The initial guess needs at least two observations, only this many were given.
+    TooFewObservations(usize),
+    /// The covariance could not be computed (`JᵀJ` singular or too few residuals).
+    SingularCovariance,
+    /// The covariance diagonal entry at this index is negative.
+    NegativeVariance(usize),
+    /// The number of sigmas does not match the number of observations.
+    SigmaCountMismatch { observations: usize, sigmas: usize },
+    /// The sigma at this index is zero, negative or NaN (an infinite sigma ignores the observation).
+    InvalidSigma(usize),
+    /// The number of observations does not match the number of model samples.
+    ObservationCountMismatch { observations: usize, samples: usize },
+    /// Monte Carlo estimates need at least two samples, only this many were requested.
+    TooFewSamples(usize),
+    /// This setting is not supported by the requested fit (e.g. in 3D).
+    Unsupported(&'static str),
+    /// This solver setting (`patience` or `initial_step`) must be positive.
+    InvalidSolverSetting(&'static str),
+}
+
+impl Display for FitError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FitError::TooFewObservations(n) => write!(f, "at least 2 observations are needed, got {}", n),
+            FitError::SingularCovariance => write!(f, "the covariance matrix is singular"),
+            FitError::NegativeVariance(i) => write!(f, "negative variance for parameter {}", i),
+            FitError::SigmaCountMismatch { observations, sigmas } => write!(f, "got {} sigmas for {} observations", sigmas, observations),
+            FitError::InvalidSigma(i) => write!(f, "sigma {} is not positive", i),
+            FitError::ObservationCountMismatch { observations, samples } => write!(
+                f,
+                "got {} observations but the model produces {} samples (check steps and stride)",
+                observations, samples,
+            ),
+            FitError::TooFewSamples(n) => write!(f, "at least 2 samples are needed, got {}", n),
+            FitError::Unsupported(setting) => write!(f, "{} is not supported by this fit", setting),
+            FitError::InvalidSolverSetting(setting) => write!(f, "{} must be positive", setting),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FitError {}
+
+/// How [`OptimizationProblem`] computes the Jacobian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum JacobianMode {
+    /// Forward mode automatic differentiation through the integrator.
+    #[default]
+    Autodiff,
+    /// Forward finite differences, one extra residual evaluation per free parameter.
+    ForwardDiff,
+    /// Central finite differences, two extra residual evaluations per free parameter.
+    CentralDiff,
+    /// Automatic differentiation of the analytic two-body propagation (the state transition
+    /// matrix of [`propagate_kepler`]) instead of the configured integrator, exact for the pure
+    /// two-body problem and ignoring the other bodies, drag and softening. Bound orbits only.
+    KeplerStm,
+}
+
+/// Why [`fit_trajectory_checked`] did not return a state.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FitFailure {
+    /// The inputs were rejected before running the solver.
+    Invalid(FitError),
+    /// The solver ran out of residual evaluations, see [`FitConfig::patience`].
+    LostPatience,
+    /// The residuals or the Jacobian became non-finite (or singular), with the solver's reason.
+    Numerical(&'static str),
+    /// Any other unsuccessful termination of the solver.
+    Solver(TerminationReason),
+}
+
+impl Display for FitFailure {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FitFailure::Invalid(e) => write!(f, "{}", e),
+            FitFailure::LostPatience => write!(f, "the fit did not converge within the allowed evaluations"),
+            FitFailure::Numerical(reason) => write!(f, "numerical failure: {}", reason),
+            FitFailure::Solver(reason) => write!(f, "the solver stopped without converging: {:?}", reason),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FitFailure {}
+
+impl From<FitError> for FitFailure {
+    fn from(e: FitError) -> Self {
+        FitFailure::Invalid(e)
+    }
+}
+
+/// Fit settings: the forward model plus the Levenberg-Marquardt solver parameters.
+///
+/// The defaults are the ones of `LevenbergMarquardt::new()`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FitConfig {
+    pub integration: IntegratorConfig,
+    /// Relative reduction of the cost below which the solver stops.
+    pub ftol: f64,
+    /// Relative change of the parameters below which the solver stops.
+    pub xtol: f64,
+    /// Orthogonality between residuals and Jacobian columns below which the solver stops.
+    pub gtol: f64,
+    /// Bounds the initial step, relative to the (scaled) initial parameters.
+    pub initial_step: f64,
+    /// The solver stops after `patience * (n + 1)` residual evaluations, `n` being the number of
+    /// fitted parameters.
+    pub patience: usize,
+    /// Which of `(pos.x, pos.y, vel.x, vel.y)` are fitted, the others keep their initial guess
+    /// value (see [`fit_trajectory_with_guess`]).
+    pub free_parameters: [bool; 4],
+    /// Lower and upper bounds of `(pos.x, pos.y, vel.x, vel.y)`, either may be infinite.
+    ///
+    /// Bounded parameters are fitted through a logistic (or exponential, for one-sided bounds)
+    /// mapping, so they stay strictly inside their bounds.
+    #[cfg_attr(feature = "serde", serde(with = "serde_bounds"))]
+    pub bounds: [(f64, f64); 4],
+    /// Finite differences can help telling errors in the dynamics from errors in the
+    /// automatic differentiation.
+    pub jacobian: JacobianMode,
+}
+
+impl FitConfig {
+    /// Number of fitted parameters, see [`FitConfig::free_parameters`].
+    pub fn num_free_parameters(&self) -> usize {
+        self.free_parameters.iter().filter(|&&free| free).count()
+    }
+
+    /// Degrees of freedom of a fit with `n` residuals, i.e. `n` minus the number of fitted
+    /// parameters, `None` if not positive.
+    pub fn degrees_of_freedom(&self, n: usize) -> Option<usize> {
+        n.checked_sub(self.num_free_parameters()).filter(|&dof| dof > 0)
+    }
+
+    /// Checks the settings [`FitConfig::solver`] would otherwise panic on.
+    pub(crate) fn validate_solver(&self) -> Result<(), FitError> {
+        if self.patience == 0 {
+            return Err(FitError::InvalidSolverSetting("patience"));
+        }
+        if self.initial_step.is_nan() || self.initial_step <= 0.0 {
+            return Err(FitError::InvalidSolverSetting("initial_step"));
+        }
+        Ok(())
+    }
+
+    pub fn solver(&self) -> LevenbergMarquardt<f64> {
+        self.solver_with_precision()
+    }
+
+    /// Same as [`FitConfig::solver`] for another scalar type, raising the tolerances to at
+    /// least `30 * F::epsilon()`.
+    pub fn solver_with_precision<F: nalgebra::RealField + num_traits::Float>(&self) -> LevenbergMarquardt<F> {
+        let tolerance = |tol: f64| num_traits::Float::max(cast::<f64, F>(tol), cast::<f64, F>(30.0) * <F as num_traits::Float>::epsilon());
+        LevenbergMarquardt::new()
+            .with_ftol(tolerance(self.ftol))
+            .with_xtol(tolerance(self.xtol))
+            .with_gtol(tolerance(self.gtol))
+            .with_stepbound(cast(self.initial_step))
+            .with_patience(self.patience)
+    }
+}
+
+impl Default for FitConfig {
+    fn default() -> Self {
+        Self {
+            integration: IntegratorConfig::default(),
+            ftol: 30.0 * f64::EPSILON,
+            xtol: 30.0 * f64::EPSILON,
+            gtol: 30.0 * f64::EPSILON,
+            initial_step: 100.0,
+            patience: 100,
+            free_parameters: [true; 4],
+            bounds: [(f64::NEG_INFINITY, f64::INFINITY); 4],
+            jacobian: JacobianMode::default(),
+        }
+    }
+}
+
+/// Infinite bounds as `None`, formats like JSON having no representation for them.
+#[cfg(feature = "serde")]
+mod serde_bounds {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(bounds: &[(f64, f64); 4], serializer: S) -> Result<S::Ok, S::Error> {
+        let finite = |bound: f64| Some(bound).filter(|bound| bound.is_finite());
+        bounds.map(|(lower, upper)| (finite(lower), finite(upper))).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[(f64, f64); 4], D::Error> {
+        let bounds = <[(Option<f64>, Option<f64>); 4]>::deserialize(deserializer)?;
+        Ok(bounds.map(|(lower, upper)| (lower.unwrap_or(f64::NEG_INFINITY), upper.unwrap_or(f64::INFINITY))))
+    }
+}
+
+pub fn fit_trajectory(observations: &[f64]) -> Result<(State<f64>, MinimizationReport<f64>), FitError> {
+    fit_trajectory_with_config(observations, &FitConfig::default())
+}
+
+pub fn fit_trajectory_with_config(observations: &[f64], config: &FitConfig) -> Result<(State<f64>, MinimizationReport<f64>), FitError> {
+    fit_trajectory_with_model(observations, &Bearing, config)
+}
+
+/// Same as [`fit_trajectory_with_config`], but starting the optimizer from `initial` instead
+/// of the unit circle heuristic.
+pub fn fit_trajectory_with_guess(observations: &[f64], initial: State<f64>, config: &FitConfig) -> Result<(State<f64>, MinimizationReport<f64>), FitError> {
+    fit(observations, None, Some(initial), &Bearing, config)
+}
+
+/// Same as [`fit_trajectory_with_config`], starting from the object at an assumed `radius`
+/// instead of on the unit circle, see [`bearing_initial_guess`].
+pub fn fit_trajectory_with_radius(observations: &[f64], radius: f64, config: &FitConfig) -> Result<(State<f64>, MinimizationReport<f64>), FitError> {
+    if observations.len() < 2 {
+        return Err(FitError::TooFewObservations(observations.len()));
+    }
+    let guess = translate(&bearing_initial_guess(observations, radius, &config.integration), &config.integration.center);
+    fit_trajectory_with_guess(observations, guess, config)
+}
+
+/// Runs the fit from the default guess and from `n_starts` randomized ones (position angle
+/// around the first bearing, radius, speed and heading), keeping the lowest final cost.
+///
+/// Also returns the index of the winning start, `0` being the default guess.
+#[cfg(feature = "std")]
+pub fn fit_trajectory_multistart(observations: &[f64], n_starts: usize, seed: u64, config: &FitConfig) -> Result<(State<f64>, MinimizationReport<f64>, usize), FitError> {
+    let (state, report) = fit_trajectory_with_config(observations, config)?;
+    let mut best = (state, report, 0);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let sense = wrap_angle(observations[1] - observations[0]).signum();
+    for start in 1..=n_starts {
+        let angle = observations[0] + rng.gen_range(-0.2..0.2);
+        let radius = rng.gen_range(0.5..10.0);
+        let speed = rng.gen_range(0.2..2.0) * (config.integration.mu / radius).sqrt();
+        let heading = angle + sense * core::f64::consts::FRAC_PI_2 + rng.gen_range(-0.5..0.5);
+        let guess = State {
+            pos: bearing_to_unit_vector(angle) * radius + config.integration.center,
+            vel: bearing_to_unit_vector(heading) * speed,
+        };
+        let (state, report) = fit_trajectory_with_guess(observations, guess, config)?;
+        if report.objective_function < best.1.objective_function {
+            best = (state, report, start);
+        }
+    }
+    Ok(best)
+}
+
+/// Same as [`fit_trajectory_with_config`], but failing unless the solver reports convergence.
+pub fn fit_trajectory_checked(observations: &[f64], config: &FitConfig) -> Result<State<f64>, FitFailure> {
+    let (state, report) = fit_trajectory_with_config(observations, config)?;
+    match report.termination {
+        reason if reason.was_successful() => Ok(state),
+        TerminationReason::LostPatience => Err(FitFailure::LostPatience),
+        TerminationReason::Numerical(reason) => Err(FitFailure::Numerical(reason)),
+        reason => Err(FitFailure::Solver(reason)),
+    }
+}
+
+/// Same as [`fit_trajectory_with_config`], also returning the initial guess the solver started
+/// from, e.g. to plot it with [`plot_fit`] when the fit diverges.
+pub fn fit_trajectory_and_guess(observations: &[f64], config: &FitConfig) -> Result<(State<f64>, MinimizationReport<f64>, State<f64>), FitError> {
+    let problem = fit_problem(observations, None, None, &Bearing, config)?;
+    let guess = problem.state().clone();
+    let (problem, report) = config.solver().minimize(problem);
+    Ok((problem.p, report, guess))
+}
+
+/// Same as [`fit_trajectory_with_config`], calling `callback` with the state, the cost
+/// `0.5 * |r|^2` and whether the solver accepted the step, for every evaluation of the
+/// residuals (including the rejected trial steps).
+pub fn fit_trajectory_with_callback(observations: &[f64], config: &FitConfig, callback: &mut dyn FnMut(&State<f64>, f64, bool)) -> Result<(State<f64>, MinimizationReport<f64>), FitError> {
+    let problem = LoggedProblem {
+        problem: fit_problem(observations, None, None, &Bearing, config)?,
+        callback: RefCell::new(callback),
+        pending: RefCell::new(None),
     };
-    let problem = OptimizationProblem {
-        p: initial_guess,
-        observed: observations,
+    let (problem, report) = config.solver().minimize(problem);
+    // the last evaluation is accepted if the solver kept its parameters
+    let accepted = problem.pending.borrow().as_ref().is_some_and(|(state, _)| Vector4::from(state) == Vector4::from(&problem.problem.p));
+    problem.report_pending(accepted);
+    Ok((problem.problem.p, report))
+}
+
+/// Both solutions of an angle-only fit, see [`fit_trajectory_with_mirror`].
+#[derive(Debug)]
+pub struct MirrorFit {
+    /// The solution with the lowest cost.
+    pub state: State<f64>,
+    pub report: MinimizationReport<f64>,
+    /// The other solution, normally of opposite orbit sense.
+    pub mirror: State<f64>,
+    pub mirror_report: MinimizationReport<f64>,
+    /// Whether the two costs are within the requested relative tolerance, i.e. the data
+    /// cannot tell them apart.
+    pub ambiguous: bool,
+}
+
+/// Fits the bearings, then refits starting from the solution with its transverse velocity
+/// reflected (flipping the orbit sense), which angle-only data often fits just as well.
+///
+/// The two solutions are flagged as [`MirrorFit::ambiguous`] if their costs differ by less
+/// than `tolerance` relative to the largest one.
+pub fn fit_trajectory_with_mirror(observations: &[f64], tolerance: f64, config: &FitConfig) -> Result<MirrorFit, FitError> {
+    let (state, report) = fit_trajectory_with_config(observations, config)?;
+    let radial = (state.pos - config.integration.center).normalize();
+    let reflected = State {
+        pos: state.pos,
+        vel: radial * (2.0 * state.vel.dot(&radial)) - state.vel,
     };
-    let (result, report) = LevenbergMarquardt::new().minimize(problem);
-    (result.p, report)
+    let (mirror, mirror_report) = fit_trajectory_with_guess(observations, reflected, config)?;
+    let (cost, mirror_cost) = (report.objective_function, mirror_report.objective_function);
+    let ambiguous = (cost - mirror_cost).abs() <= tolerance * cost.max(mirror_cost);
+    Ok(if mirror_cost < cost {
+        MirrorFit { state: mirror, report: mirror_report, mirror: state, mirror_report: report, ambiguous }
+    } else {
+        MirrorFit { state, report, mirror, mirror_report, ambiguous }
+    })
+}
+
+/// Same as [`fit_trajectory_with_config`], running the whole fit with the scalar type `F`
+/// (e.g. `f32`) to compare precisions.
+pub fn fit_trajectory_with_precision<F>(observations: &[f64], config: &FitConfig) -> Result<(State<F>, MinimizationReport<F>), FitError>
+where
+    F: nalgebra::RealField + num_traits::Float + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    Differential<F, Vector4<F>>: Real + Debug + AddAssign + DivAssign + MulAssign + From<F> + 'static,
+{
+    let problem = fit_problem(observations, None, None, &Bearing, config)?;
+    let initial_guess = State { pos: problem.p.pos.map(cast), vel: problem.p.vel.map(cast) };
+    let problem = OptimizationProblem::new(initial_guess, observations, &Bearing, &config.integration)
+        .with_free_parameters(config.free_parameters)
+        .with_bounds(config.bounds)
+        .with_jacobian_mode(config.jacobian);
+    let (problem, report) = config.solver_with_precision().minimize(problem);
+    Ok((problem.p, report))
+}
+
+/// Fits time-tagged `(time, bearing)` observations, see [`TimedBearing`].
+pub fn fit_trajectory_timed(observations: &[(f64, f64)], config: &FitConfig) -> Result<(State<f64>, MinimizationReport<f64>), FitError> {
+    fit_trajectory_with_model(observations, &TimedBearing, config)
 }
 
+/// One tracking pass: bearings on the usual sample grid, starting `epoch_offset` after the
+/// start of the observations (i.e. after [`IntegratorConfig::epoch_offset`]).
 #[derive(Debug, Clone)]
+pub struct ObservationArc {
+    pub epoch_offset: f64,
+    /// Bearing `i` is taken at `epoch_offset + dt + i * dt * stride`, any number of them.
+    pub observations: Vec<f64>,
+}
+
+/// Fits a single state against several disjoint arcs, propagated from the common epoch and
+/// with their residuals concatenated, through [`fit_trajectory_timed`].
+pub fn fit_trajectory_arcs(arcs: &[ObservationArc], config: &FitConfig) -> Result<(State<f64>, MinimizationReport<f64>), FitError> {
+    let sample_interval = config.integration.dt * config.integration.stride.max(1) as f64;
+    let observations = arcs.iter()
+        .flat_map(|arc| arc.observations.iter().enumerate().map(move |(i, &bearing)| {
+            (arc.epoch_offset + config.integration.dt + i as f64 * sample_interval, bearing)
+        }))
+        .collect::<Vec<_>>();
+    fit_trajectory_timed(&observations, config)
+}
+
+/// Fits the initial state against observations of any [`ObservationModel`].
+pub fn fit_trajectory_with_model<M: ObservationModel>(observations: &[M::Observation], model: &M, config: &FitConfig) -> Result<(State<f64>, MinimizationReport<f64>), FitError> {
+    fit(observations, None, None, model, config)
+}
+
+/// Same as [`fit_trajectory_with_model`], integrating the trajectory under `dynamics` (see
+/// [`integrate_trajectory_with`]) instead of the force model of the configuration.
+pub fn fit_trajectory_with_dynamics<M: ObservationModel, D>(observations: &[M::Observation], model: &M, dynamics: &D, config: &FitConfig) -> Result<(State<f64>, MinimizationReport<f64>), FitError>
+where
+    D: Dynamics<f64> + Dynamics<Differential<f64, Vector4<f64>>>,
+{
+    let problem = fit_problem(observations, None, None, model, config)?.with_dynamics(dynamics);
+    let (problem, report) = config.solver().minimize(problem);
+    Ok((problem.p, report))
+}
+
+/// Weighted least squares fit: the residuals of observation `i` are divided by `sigmas[i]`,
+/// which must be positive.
+pub fn fit_trajectory_weighted<M: ObservationModel>(observations: &[M::Observation], sigmas: &[f64], model: &M, config: &FitConfig) -> Result<(State<f64>, MinimizationReport<f64>), FitError> {
+    fit(observations, Some(sigmas), None, model, config)
+}
+
+/// Robust fit with Huber weighting, by iteratively reweighted least squares.
+///
+/// Observations whose residual norm exceeds `delta` get a weight `delta / |r|`. Also returns
+/// the indices of the observations that ended up down-weighted, likely outliers.
+pub fn fit_trajectory_huber<M: ObservationModel>(observations: &[M::Observation], delta: f64, model: &M, config: &FitConfig) -> Result<(State<f64>, MinimizationReport<f64>, Vec<usize>), FitError> {
+    let (mut state, mut report) = fit(observations, None, None, model, config)?;
+    let mut sigmas = vec![1.0; observations.len()];
+    for _ in 0..10 {
+        let problem = OptimizationProblem::new(state.clone(), observations, model, &config.integration);
+        let new_sigmas = problem.residual_norms(&state).into_iter()
+            .map(|r| if r <= delta { 1.0 } else { (r / delta).sqrt() })
+            .collect::<Vec<_>>();
+        let converged = new_sigmas.iter().zip(&sigmas).all(|(new, old)| (new - old).abs() <= 1e-6 * old);
+        sigmas = new_sigmas;
+        if converged {
+            break;
+        }
+        (state, report) = fit(observations, Some(&sigmas), Some(state), model, config)?;
+    }
+    let downweighted = sigmas.iter().enumerate().filter(|(_, &sigma)| sigma > 1.0).map(|(i, _)| i).collect();
+    Ok((state, report, downweighted))
+}
+
+/// Fit with iterative sigma clipping: observations whose residual exceeds `k` times the RMS
+/// residual of the retained ones are rejected and the fit is repeated, for at most `max_rounds`
+/// refits or until the rejected set stops changing. Also returns the rejected indices.
+///
+/// Rejected observations get an infinite sigma, so they keep their place on the sample grid.
+pub fn fit_trajectory_clipped(observations: &[f64], k: f64, max_rounds: usize, config: &FitConfig) -> Result<(State<f64>, MinimizationReport<f64>, Vec<usize>), FitError> {
+    let (mut state, mut report) = fit_trajectory_with_config(observations, config)?;
+    let mut rejected = Vec::new();
+    for _ in 0..max_rounds {
+        let residuals = compute_residuals(&state, observations, &config.integration);
+        let retained = residuals.iter().enumerate().filter(|(i, _)| !rejected.contains(i)).map(|(_, r)| r.powi(2)).collect::<Vec<_>>();
+        let rms = (retained.iter().sum::<f64>() / retained.len().max(1) as f64).sqrt();
+        let new_rejected = residuals.iter().enumerate().filter(|(_, r)| r.abs() > k * rms).map(|(i, _)| i).collect::<Vec<_>>();
+        if new_rejected == rejected {
+            break;
+        }
+        rejected = new_rejected;
+        let sigmas = (0..observations.len()).map(|i| if rejected.contains(&i) { f64::INFINITY } else { 1.0 }).collect::<Vec<_>>();
+        (state, report) = fit(observations, Some(&sigmas), Some(state), &Bearing, config)?;
+    }
+    Ok((state, report, rejected))
+}
+
+pub(crate) fn fit<M: ObservationModel>(observations: &[M::Observation], sigmas: Option<&[f64]>, initial_guess: Option<State<f64>>, model: &M, config: &FitConfig) -> Result<(State<f64>, MinimizationReport<f64>), FitError> {
+    let problem = fit_problem(observations, sigmas, initial_guess, model, config)?;
+    let (problem, report) = config.solver().minimize(problem);
+    Ok((problem.p, report))
+}
+
+/// Checks the inputs and sets up the problem solved by [`fit`].
+fn validate_sigmas(observations: usize, sigmas: &[f64]) -> Result<(), FitError> {
+    if sigmas.len() != observations {
+        return Err(FitError::SigmaCountMismatch { observations, sigmas: sigmas.len() });
+    }
+    if let Some(i) = sigmas.iter().position(|&sigma| sigma.is_nan() || sigma <= 0.0) {
+        return Err(FitError::InvalidSigma(i));
+    }
+    Ok(())
+}
+
+fn fit_problem<'a, M: ObservationModel>(observations: &'a [M::Observation], sigmas: Option<&'a [f64]>, initial_guess: Option<State<f64>>, model: &'a M, config: &'a FitConfig) -> Result<OptimizationProblem<'a, M>, FitError> {
+    if observations.len() < 2 {
+        return Err(FitError::TooFewObservations(observations.len()));
+    }
+    if model.observation_times(observations).is_none() && observations.len() != config.integration.num_samples() {
+        return Err(FitError::ObservationCountMismatch { observations: observations.len(), samples: config.integration.num_samples() });
+    }
+    if let Some(sigmas) = sigmas {
+        validate_sigmas(observations.len(), sigmas)?;
+    }
+    config.validate_solver()?;
+    let initial_guess = initial_guess.unwrap_or_else(|| translate(&model.initial_guess(observations, &config.integration), &config.integration.center));
+    let mut problem = OptimizationProblem::new(initial_guess, observations, model, &config.integration)
+        .with_free_parameters(config.free_parameters)
+        .with_bounds(config.bounds)
+        .with_jacobian_mode(config.jacobian);
+    if let Some(sigmas) = sigmas {
+        problem = problem.with_sigmas(sigmas);
+    }
+    Ok(problem)
+}
+
+pub fn fit_trajectory_with_covariance(observations: &[f64], config: &FitConfig) -> Result<(State<f64>, Option<Matrix4<f64>>, MinimizationReport<f64>), FitError> {
+    let (state, report) = fit_trajectory_with_config(observations, config)?;
+    let covariance = covariance(&state, observations, config);
+    Ok((state, covariance, report))
+}
+
+pub fn fit_trajectory_with_uncertainty(observations: &[f64], config: &FitConfig) -> Result<(State<f64>, StateUncertainty, MinimizationReport<f64>), FitError> {
+    let (state, covariance, report) = fit_trajectory_with_covariance(observations, config)?;
+    let uncertainty = StateUncertainty::from_covariance(&covariance.ok_or(FitError::SingularCovariance)?)?;
+    Ok((state, uncertainty, report))
+}
+
+/// 1-sigma standard deviations of the fitted state components.
+#[derive(Debug, Clone)]
+pub struct StateUncertainty {
+    pub pos_x: f64,
+    pub pos_y: f64,
+    pub vel_x: f64,
+    pub vel_y: f64,
+}
+
+impl StateUncertainty {
+    pub fn from_covariance(covariance: &Matrix4<f64>) -> Result<Self, FitError> {
+        let mut sigma = [0.0; 4];
+        for (i, s) in sigma.iter_mut().enumerate() {
+            let variance = covariance[(i, i)];
+            if variance.is_nan() || variance < 0.0 {
+                return Err(FitError::NegativeVariance(i));
+            }
+            *s = variance.sqrt();
+        }
+        Ok(Self {
+            pos_x: sigma[0],
+            pos_y: sigma[1],
+            vel_x: sigma[2],
+            vel_y: sigma[3],
+        })
+    }
+}
+
+/// Root mean square of the bearing residuals (radians) of `state`.
+pub fn residual_rms(state: &State<f64>, observations: &[f64], config: &IntegratorConfig) -> f64 {
+    let residuals = compute_residuals(state, observations, config);
+    (residuals.iter().map(|r| r * r).sum::<f64>() / residuals.len() as f64).sqrt()
+}
+
+/// Reduced chi-square `sum((r / sigma)^2) / dof` of the bearing residuals of `state`, close to 1
+/// when the model fits within the noise, see [`FitConfig::degrees_of_freedom`].
+///
+/// NaN if there are not more residuals than fitted parameters. The sigmas are checked as in
+/// [`fit_trajectory_weighted`].
+pub fn reduced_chi_square(state: &State<f64>, observations: &[f64], sigmas: &[f64], config: &FitConfig) -> Result<f64, FitError> {
+    validate_sigmas(observations.len(), sigmas)?;
+    let residuals = model_residuals(state, observations, Some(sigmas), &Bearing, &config.integration, &config.integration);
+    Ok(match config.degrees_of_freedom(residuals.len()) {
+        Some(dof) => residuals.iter().map(|r| r * r).sum::<f64>() / dof as f64,
+        None => f64::NAN,
+    })
+}
+
+/// Jacobian of the bearing residuals with respect to `(pos.x, pos.y, vel.x, vel.y)`, by
+/// automatic differentiation as used by the fit.
+///
+/// Evaluated at a fitted state, row `i` tells how strongly residual `i` constrains each
+/// parameter.
+pub fn jacobian(state: &State<f64>, observations: &[f64], config: &IntegratorConfig) -> OMatrix<f64, Dyn, U4> {
+    model_jacobian(state, observations, &Bearing, config)
+}
+
+/// Same as [`jacobian`], for the (unweighted) residuals of any [`ObservationModel`].
+///
+/// Non-finite entries are kept as they are, instead of being rejected as during the fit.
+pub fn model_jacobian<M: ObservationModel>(state: &State<f64>, observations: &[M::Observation], model: &M, config: &IntegratorConfig) -> OMatrix<f64, Dyn, U4> {
+    let problem = OptimizationProblem::new(state.clone(), observations, model, config);
+    let residuals = problem.dual_residuals();
+    OMatrix::<f64, Dyn, U4>::from_fn_generic(Dyn(residuals.len()), U4::name(), |i, j| residuals[i].derivative[j])
+}
+
+/// Condition number of the Jacobian (largest over smallest singular value), infinite if it is
+/// rank deficient.
+pub fn condition_number(jacobian: &OMatrix<f64, Dyn, U4>) -> f64 {
+    free_condition_number(jacobian, [true; 4])
+}
+
+/// Same as [`condition_number`], restricted to the columns of the fitted parameters.
+fn free_condition_number(jacobian: &OMatrix<f64, Dyn, U4>, free_parameters: [bool; 4]) -> f64 {
+    let columns: Vec<_> = jacobian.column_iter().zip(free_parameters).filter(|(_, free)| *free).map(|(column, _)| column.into_owned()).collect();
+    if columns.is_empty() || jacobian.nrows() < columns.len() {
+        return f64::INFINITY;
+    }
+    // from the singular values of `J` itself, forming `JᵀJ` would square the condition number
+    // and lose the small singular values to rounding
+    let singular_values = OMatrix::<f64, Dyn, Dyn>::from_columns(&columns).singular_values();
+    if singular_values.min() <= 0.0 {
+        return f64::INFINITY;
+    }
+    singular_values.max() / singular_values.min()
+}
+
+/// How well the observations constrain a fitted state, see [`fit_trajectory_with_conditioning`].
+#[derive(Debug, Clone)]
+pub struct Conditioning {
+    /// [`condition_number`] of the Jacobian at the solution.
+    pub condition_number: f64,
+    /// Whether the condition number exceeds the requested threshold, meaning some combination
+    /// of the parameters is nearly unobservable and the solution is unreliable.
+    pub ill_conditioned: bool,
+}
+
+/// Same as [`fit_trajectory_with_config`], also checking the conditioning of the solution
+/// against `threshold` (e.g. `1e8`). Only the columns of the fitted parameters (see
+/// [`FitConfig::free_parameters`]) are considered.
+pub fn fit_trajectory_with_conditioning(observations: &[f64], threshold: f64, config: &FitConfig) -> Result<(State<f64>, MinimizationReport<f64>, Conditioning), FitError> {
+    let (state, report) = fit_trajectory_with_config(observations, config)?;
+    let condition_number = free_condition_number(&jacobian(&state, observations, &config.integration), config.free_parameters);
+    let conditioning = Conditioning { condition_number, ill_conditioned: condition_number.is_nan() || condition_number > threshold };
+    Ok((state, report, conditioning))
+}
+
+/// Same as [`jacobian`], by central finite differences with the given parameter `step`.
+pub fn finite_difference_jacobian(state: &State<f64>, observations: &[f64], config: &IntegratorConfig, step: f64) -> OMatrix<f64, Dyn, U4> {
+    OptimizationProblem::new(state.clone(), observations, &Bearing, config).finite_difference_jacobian(true, Some(step))
+}
+
+/// Parameter covariance `sigma^2 * (JᵀJ)^(-1)` at `state`, with `sigma^2` the reduced chi-square.
+///
+/// Parameters that are not fitted (see [`FitConfig::free_parameters`]) get zero rows and
+/// columns. Returns `None` if there are not more residuals than fitted parameters or if `JᵀJ`
+/// is (nearly) singular.
+pub fn covariance(state: &State<f64>, observations: &[f64], config: &FitConfig) -> Option<Matrix4<f64>> {
+    let problem = OptimizationProblem::new(state.clone(), observations, &Bearing, &config.integration)
+        .with_free_parameters(config.free_parameters);
+    let residuals = problem.residuals(state);
+    let dof = config.degrees_of_freedom(residuals.len())?;
+    let sigma2 = residuals.iter().map(|r| r * r).sum::<f64>() / dof as f64;
+    let jacobian = problem.jacobian()?;
+    let mut jtj = jacobian.transpose() * &jacobian;
+    // the zero columns of the fixed parameters would make `JᵀJ` singular, a unit diagonal
+    // decouples them and their rows and columns are cleared afterwards
+    let fixed = (0..4).filter(|&i| !config.free_parameters[i]).collect::<Vec<_>>();
+    for &i in &fixed {
+        jtj[(i, i)] = 1.0;
+    }
+    let eigenvalues = jtj.symmetric_eigenvalues();
+    if eigenvalues.min() <= eigenvalues.max() * f64::EPSILON {
+        return None;
+    }
+    let mut covariance = jtj.try_inverse()? * sigma2;
+    for &i in &fixed {
+        covariance.row_mut(i).fill(0.0);
+        covariance.column_mut(i).fill(0.0);
+    }
+    Some(covariance)
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "Vector2<T>: serde::Serialize",
+    deserialize = "Vector2<T>: serde::Deserialize<'de>",
+)))]
 pub struct State<T = f64> {
     pub pos: Vector2<T>,
     pub vel: Vector2<T>,
 }
 
-pub fn integrate_trajectory_euler<T>(initial_state: &State<T>) -> impl Iterator<Item = State<T>>
+impl State<f64> {
+    /// Compact summary, with `|r|` and the orbital energy measured about the central body of
+    /// `config` (its `center` and `mu`).
+    pub fn display(&self, config: &IntegratorConfig) -> impl Display + '_ {
+        StateDisplay { state: self, mu: config.mu, center: config.center }
+    }
+
+    /// Parses a whitespace separated `x y vx vy` state vector.
+    pub fn from_state_vector_str(s: &str) -> Result<Self, ParseStateError> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 4 {
+            return Err(ParseStateError::FieldCount(fields.len()));
+        }
+        let mut values = [0.0; 4];
+        for (i, (field, value)) in fields.iter().zip(&mut values).enumerate() {
+            *value = field.parse().map_err(|_| ParseStateError::InvalidNumber { index: i, field: field.to_string() })?;
+            if !value.is_finite() {
+                return Err(ParseStateError::NonFinite(i));
+            }
+        }
+        Ok(Vector4::from(values).into())
+    }
+}
+
+impl core::str::FromStr for State<f64> {
+    type Err = ParseStateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_state_vector_str(s)
+    }
+}
+
+/// Error of [`State::from_state_vector_str`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseStateError {
+    /// Expected 4 fields (`x y vx vy`), got this many.
+    FieldCount(usize),
+    /// The field at this index is not a number.
+    InvalidNumber { index: usize, field: String },
+    /// The field at this index is infinite or NaN.
+    NonFinite(usize),
+}
+
+impl Display for ParseStateError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseStateError::FieldCount(n) => write!(f, "expected 4 fields `x y vx vy`, got {}", n),
+            ParseStateError::InvalidNumber { index, field } => write!(f, "field {} is not a number: {:?}", index + 1, field),
+            ParseStateError::NonFinite(index) => write!(f, "field {} is not finite", index + 1),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseStateError {}
+
+struct StateDisplay<'a> {
+    state: &'a State<f64>,
+    mu: f64,
+    center: Vector2<f64>,
+}
+
+impl Display for StateDisplay<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let State { pos, vel } = self.state;
+        let relative = translate(self.state, &-self.center);
+        let (r, v) = (relative.pos.norm(), vel.norm());
+        write!(
+            f,
+            "pos=({:.2}, {:.2}) vel=({:.2}, {:.2}) |r|={:.2} |v|={:.2} E={:.4}",
+            pos[0], pos[1], vel[0], vel[1], r, v, specific_energy(&relative, self.mu),
+        )
+    }
+}
+
+/// From `(pos.x, pos.y, vel.x, vel.y)`, the parameter order of the fit.
+impl<T: Scalar + Copy> From<Vector4<T>> for State<T> {
+    fn from(params: Vector4<T>) -> Self {
+        State {
+            pos: Vector2::new(params[0], params[1]),
+            vel: Vector2::new(params[2], params[3]),
+        }
+    }
+}
+
+/// To `(pos.x, pos.y, vel.x, vel.y)`, the parameter order of the fit.
+impl<T: Scalar + Copy> From<&State<T>> for Vector4<T> {
+    fn from(state: &State<T>) -> Self {
+        Vector4::new(state.pos[0], state.pos[1], state.vel[0], state.vel[1])
+    }
+}
+
+/// Uses the default central body (`mu = 1` at the origin), see [`State::display`] for others.
+impl Display for State<f64> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.display(&IntegratorConfig::default()))
+    }
+}
+
+pub fn acceleration<T>(pos: &Vector2<T>, mu: T, epsilon: T) -> Vector2<T>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let dist2 = pos[0].powi(2) + pos[1].powi(2) + epsilon.powi(2);
+    -pos * mu / dist2.sqrt().powi(3)
+}
+
+/// Quadratic drag `-k * |v| * v`.
+pub fn drag_acceleration<T, const D: usize>(vel: &SVector<T, D>, k: T) -> SVector<T, D>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let speed = vel.iter().fold(T::zero(), |sum, &v| sum + v * v).sqrt();
+    -vel * (k * speed)
+}
+
+/// Gravity of all the bodies plus drag, as a function of position and velocity, skipping the
+/// drag term when disabled (its derivative is not defined at zero velocity).
+pub(crate) fn total_acceleration<T>(config: &IntegratorConfig) -> impl Fn(&Vector2<T>, &Vector2<T>) -> Vector2<T>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let mu = T::from(config.mu).unwrap();
+    let epsilon = T::from(config.epsilon).unwrap();
+    let k = T::from(config.drag).unwrap();
+    let with_drag = config.drag != 0.0;
+    // stored negated, as `T` only guarantees `AddAssign`
+    let minus_center = config.center.map(|x| T::from(-x).unwrap());
+    let omega = T::from(config.omega).unwrap();
+    let rotating = config.omega != 0.0;
+    let bodies = config.bodies.iter()
+        .map(|body| (body.pos.map(|x| T::from(-x).unwrap()), T::from(body.mu).unwrap()))
+        .collect::<Vec<_>>();
+    move |pos: &Vector2<T>, vel: &Vector2<T>| {
+        let mut acc = acceleration(&(pos + minus_center), mu, epsilon);
+        for (minus_body_pos, body_mu) in &bodies {
+            acc += acceleration(&(pos + minus_body_pos), *body_mu, epsilon);
+        }
+        if with_drag {
+            acc += drag_acceleration(vel, k);
+        }
+        if rotating {
+            let two = T::from(2.0).unwrap();
+            acc += (pos + minus_center) * (omega * omega);
+            acc += Vector2::new(two * omega * vel[1], -two * omega * vel[0]);
+        }
+        acc
+    }
+}
+
+pub(crate) fn euler_step<T, V>(pos: &mut V, vel: &mut V, dt: T, acceleration: &dyn Fn(&V, &V) -> V)
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    V: Copy + Add<Output = V> + Mul<T, Output = V> + AddAssign,
+{
+    let acc = acceleration(pos, vel);
+    *pos += *vel * dt;
+    *vel += acc * dt;
+}
+
+/// Semi-implicit Euler: the velocity is updated first and moves the position, which keeps the
+/// energy error bounded instead of drifting as with [`euler_step`].
+pub(crate) fn symplectic_euler_step<T, V>(pos: &mut V, vel: &mut V, dt: T, acceleration: &dyn Fn(&V, &V) -> V)
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    V: Copy + Add<Output = V> + Mul<T, Output = V> + AddAssign,
+{
+    *vel += acceleration(pos, vel) * dt;
+    *pos += *vel * dt;
+}
+
+pub(crate) fn rk4_step<T, V>(pos: &mut V, vel: &mut V, dt: T, acceleration: &dyn Fn(&V, &V) -> V)
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    V: Copy + Add<Output = V> + Mul<T, Output = V> + AddAssign,
+{
+    let two = T::from(2.0).unwrap();
+    let half_dt = dt / two;
+    let (k1_pos, k1_vel) = (*vel, acceleration(pos, vel));
+    let k2_pos = *vel + k1_vel * half_dt;
+    let k2_vel = acceleration(&(*pos + k1_pos * half_dt), &k2_pos);
+    let k3_pos = *vel + k2_vel * half_dt;
+    let k3_vel = acceleration(&(*pos + k2_pos * half_dt), &k3_pos);
+    let k4_pos = *vel + k3_vel * dt;
+    let k4_vel = acceleration(&(*pos + k3_pos * dt), &k4_pos);
+    let sixth_dt = dt / T::from(6.0).unwrap();
+    *pos += (k1_pos + (k2_pos + k3_pos) * two + k4_pos) * sixth_dt;
+    *vel += (k1_vel + (k2_vel + k3_vel) * two + k4_vel) * sixth_dt;
+}
+
+pub(crate) fn integrate_with_step<'a, T>(initial_state: &State<T>, config: &IntegratorConfig, acc: impl Fn(&Vector2<T>, &Vector2<T>) -> Vector2<T> + 'a, step: fn(&mut Vector2<T>, &mut Vector2<T>, T, &dyn Fn(&Vector2<T>, &Vector2<T>) -> Vector2<T>)) -> impl Iterator<Item = State<T>> + 'a
 where
     T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
 {
     let mut state = initial_state.clone();
-    let dt = T::from(DT).unwrap();
-    std::iter::from_fn(move || {
-        let dist2 = state.pos[0].powi(2) + state.pos[1].powi(2);
-        let acc = -state.pos / dist2.sqrt().powi(3);
-        state.pos += state.vel * dt;
-        state.vel += acc * dt;
+    let dt = T::from(config.dt).unwrap();
+    core::iter::from_fn(move || {
+        step(&mut state.pos, &mut state.vel, dt, &acc);
         Some(state.clone())
-    }).take(120)
+    }).take(config.steps)
+}
+
+/// Integrates the trajectory with the forward model selected in `config`, starting
+/// `epoch_offset` after the epoch of `initial_state`.
+pub fn integrate_trajectory<T>(initial_state: &State<T>, config: &IntegratorConfig) -> impl Iterator<Item = State<T>>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let start;
+    let initial_state = if config.epoch_offset != 0.0 {
+        start = state_at(initial_state, config.epoch_offset, config);
+        &start
+    } else {
+        initial_state
+    };
+    match config.integrator {
+        Integrator::Euler => Box::new(integrate_trajectory_euler(initial_state, config)) as Box<dyn Iterator<Item = State<T>>>,
+        Integrator::Rk4 => Box::new(integrate_trajectory_rk4(initial_state, config)),
+        Integrator::Kepler => Box::new(propagate_trajectory_kepler(initial_state, config)),
+        Integrator::Rkf45 => Box::new(integrate_trajectory_rkf45(initial_state, config)),
+        Integrator::SymplecticEuler => Box::new(integrate_trajectory_symplectic_euler(initial_state, config)),
+    }
+}
+
+pub fn integrate_trajectory_euler<T>(initial_state: &State<T>, config: &IntegratorConfig) -> impl Iterator<Item = State<T>>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    integrate_with_step(initial_state, config, total_acceleration(config), euler_step)
+}
+
+/// Same as [`integrate_trajectory_euler`] with [`Integrator::SymplecticEuler`] steps, for the
+/// same cost but a much more stable orbit.
+pub fn integrate_trajectory_symplectic_euler<T>(initial_state: &State<T>, config: &IntegratorConfig) -> impl Iterator<Item = State<T>>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    integrate_with_step(initial_state, config, total_acceleration(config), symplectic_euler_step)
+}
+
+pub fn integrate_trajectory_rk4<T>(initial_state: &State<T>, config: &IntegratorConfig) -> impl Iterator<Item = State<T>>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    integrate_with_step(initial_state, config, total_acceleration(config), rk4_step)
+}
+
+pub fn sampled_trajectory<T>(initial_state: &State<T>, config: &IntegratorConfig) -> impl Iterator<Item = Vector2<T>>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    integrate_trajectory(initial_state, config).step_by(config.stride.max(1)).map(|s| s.pos)
+}
+
+/// Positions of [`integrate_trajectory`] tagged with their time, `epoch_offset + (i + 1) * dt`
+/// for step `i`.
+pub fn trajectory_points(initial_state: &State<f64>, config: &IntegratorConfig) -> Vec<(f64, Vector2<f64>)> {
+    integrate_trajectory(initial_state, config)
+        .enumerate()
+        .map(|(i, s)| (config.epoch_offset + (i + 1) as f64 * config.dt, s.pos))
+        .collect()
+}
+
+/// State at the fraction `frac` of the way from `a` to `b`, two states `dt` apart (e.g. adjacent
+/// steps of [`integrate_trajectory`]).
+///
+/// Cubic Hermite interpolation as in [`Integrator::Rkf45`]: the position from the velocities of
+/// both states and the velocity from their accelerations under `config`, much more accurate than
+/// a linear interpolation.
+pub fn interpolate_state(a: &State<f64>, b: &State<f64>, frac: f64, dt: f64, config: &IntegratorConfig) -> State<f64> {
+    hermite(a, b, frac, dt, &total_acceleration(config))
+}
+
+/// States at arbitrary `times` since the epoch of `initial_state` (ignoring `epoch_offset`), in
+/// any order and on either side of the epoch.
+///
+/// Integrates on the same `dt` grid as [`integrate_trajectory`] (`-dt` for the times of the
+/// opposite sign), with a final partial step to each time. [`Integrator::Rkf45`] steps as RK4
+/// here, and [`Integrator::Kepler`] propagates analytically.
+pub fn states_at<T>(initial_state: &State<T>, times: &[f64], config: &IntegratorConfig) -> Vec<State<T>>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    if config.integrator == Integrator::Kepler {
+        let mu = T::from(config.mu).unwrap();
+        let relative = translate(initial_state, &-config.center);
+        return times.iter().map(|&t| translate(&propagate_kepler(&relative, T::from(t).unwrap(), mu), &config.center)).collect();
+    }
+    states_with(initial_state, times, config, &total_acceleration(config))
+}
+
+/// [`states_at`] for any acceleration `acc(pos, vel)`, stepping [`Integrator::Kepler`] as RK4.
+pub(crate) fn states_with<T>(initial_state: &State<T>, times: &[f64], config: &IntegratorConfig, acc: &dyn Fn(&Vector2<T>, &Vector2<T>) -> Vector2<T>) -> Vec<State<T>>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let step = match config.integrator {
+        Integrator::Euler => euler_step::<T, Vector2<T>> as fn(&mut Vector2<T>, &mut Vector2<T>, T, &dyn Fn(&Vector2<T>, &Vector2<T>) -> Vector2<T>),
+        Integrator::SymplecticEuler => symplectic_euler_step::<T, Vector2<T>>,
+        _ => rk4_step::<T, Vector2<T>>,
+    };
+    // the times on the other side of the epoch are stepped separately, with `-dt`
+    let reversed = IntegratorConfig { dt: -config.dt, ..config.clone() };
+    let mut states = vec![initial_state.clone(); times.len()];
+    for config in [config, &reversed] {
+        let dt = T::from(config.dt).unwrap();
+        let mut order = (0..times.len()).filter(|&i| times[i] / config.dt > 0.0).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| (times[a] / config.dt).total_cmp(&(times[b] / config.dt)));
+        let (mut state, mut n) = (initial_state.clone(), 0);
+        for i in order {
+            while (n + 1) as f64 <= times[i] / config.dt + 1e-9 {
+                step(&mut state.pos, &mut state.vel, dt, acc);
+                n += 1;
+            }
+            states[i] = state.clone();
+            let remainder = times[i] - config.dt * n as f64;
+            if remainder / config.dt > 1e-9 {
+                step(&mut states[i].pos, &mut states[i].vel, T::from(remainder).unwrap(), acc);
+            }
+        }
+    }
+    states
 }
 
-pub fn sampled_trajectory<T>(initial_state: &State<T>) -> impl Iterator<Item = Vector2<T>>
+/// State at a single time `t`, see [`states_at`].
+pub fn state_at<T>(initial_state: &State<T>, t: f64, config: &IntegratorConfig) -> State<T>
 where
     T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
 {
-    integrate_trajectory_euler(initial_state).step_by(5).map(|s| s.pos)
+    states_at(initial_state, &[t], config).remove(0)
 }
 
+/// Bearings of the sampled positions; the bearing of a position at the origin is meaningless
+/// (`0` by the `atan2` convention), see [`observe_checked`].
 pub fn observe<'a, T>(sampled_trajectory: &'a [Vector2<T>]) -> impl Iterator<Item = T> + 'a
 where
     T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
 {
-    sampled_trajectory.iter().map(|p| p[1].atan2(p[0]))
+    sampled_trajectory.iter().map(bearing)
+}
+
+/// Same as [`observe`], but failing with [`Error::UndefinedBearing`] if a sample is closer than
+/// `min_range` to the origin, where the bearing is undefined.
+pub fn observe_checked<T>(sampled_trajectory: &[Vector2<T>], min_range: f64) -> Result<Vec<T>, Error>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let min_range = T::from(min_range).unwrap();
+    sampled_trajectory.iter().enumerate()
+        .map(|(i, pos)| if range(pos) < min_range { Err(Error::UndefinedBearing(i)) } else { Ok(bearing(pos)) })
+        .collect()
+}
+
+/// Bearings of the sampled positions tagged with their time, for samples of
+/// [`sampled_trajectory`] from a state at time `t0`: sample `i` is at `t0 + (i * stride + 1) * dt`.
+///
+/// The result can be fitted with [`fit_trajectory_timed`].
+pub fn observe_timed(sampled_trajectory: &[Vector2<f64>], t0: f64, dt: f64, stride: usize) -> Vec<(f64, f64)> {
+    sampled_trajectory.iter().enumerate()
+        .map(|(i, pos)| (t0 + (i * stride.max(1) + 1) as f64 * dt, bearing(pos)))
+        .collect()
+}
+
+pub fn observe_range<'a, T>(sampled_trajectory: &'a [Vector2<T>]) -> impl Iterator<Item = T> + 'a
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    sampled_trajectory.iter().map(range)
+}
+
+/// Wraps an angle into `[-π, π]`, keeping the derivative continuous across the cut.
+pub fn wrap_angle<T>(angle: T) -> T
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    angle.sin().atan2(angle.cos())
 }
 
-struct OptimizationProblem<'a> {
-    p: State<f64>,
-    observed: &'a Vec<f64>,
+/// Model bearings at the sample times, index-aligned with the observations used by the fit.
+pub fn predicted_observations(state: &State<f64>, config: &IntegratorConfig) -> Vec<f64> {
+    let sampled_trajectory = sampled_trajectory(state, config).map(|pos| pos - config.center).collect::<Vec<_>>();
+    observe(&sampled_trajectory).collect()
 }
 
-impl<'a> OptimizationProblem<'a> {
+/// Bearing residuals `observed - predicted` of `state`, for any scalar type (e.g. `f64` or
+/// the `Differential` used for the Jacobian).
+pub fn compute_residuals<T>(state: &State<T>, observations: &[f64], config: &IntegratorConfig) -> Vec<T>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    model_residuals(state, observations, None, &Bearing, config, config)
+}
+
+/// Model positions matching `observations`, at their times if the model has any, at the
+/// model samples otherwise, moving under `dynamics`.
+fn model_positions<T, M: ObservationModel, D: Dynamics<T>>(state: &State<T>, observations: &[M::Observation], model: &M, dynamics: &D, config: &IntegratorConfig) -> Vec<Vector2<T>>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let minus_center = config.center.map(|x| T::from(-x).unwrap());
+    match model.observation_times(observations) {
+        Some(times) => {
+            let times = times.into_iter().map(|t| config.epoch_offset + t).collect::<Vec<_>>();
+            dynamics.states_at(state, &times, config).into_iter().map(|s| s.pos + minus_center).collect()
+        }
+        None => dynamics.trajectory(state, config).into_iter().step_by(config.stride.max(1)).map(|s| s.pos + minus_center).collect(),
+    }
+}
+
+/// `state` translated by `offset`.
+pub(crate) fn translate<T>(state: &State<T>, offset: &Vector2<f64>) -> State<T>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    State {
+        pos: state.pos + offset.map(|x| T::from(x).unwrap()),
+        vel: state.vel,
+    }
+}
+
+fn model_residuals<T, M: ObservationModel, D: Dynamics<T>>(state: &State<T>, observations: &[M::Observation], sigmas: Option<&[f64]>, model: &M, dynamics: &D, config: &IntegratorConfig) -> Vec<T>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let mut residuals = Vec::with_capacity(observations.len());
+    for (i, (observed, pos)) in observations.iter().zip(model_positions(state, observations, model, dynamics, config)).enumerate() {
+        let start = residuals.len();
+        model.residuals(observed, &pos, &mut residuals);
+        if let Some(sigmas) = sigmas {
+            let weight = T::from(sigmas[i].recip()).unwrap();
+            for r in &mut residuals[start..] {
+                *r *= weight;
+            }
+        }
+    }
+    residuals
+}
+
+/// The least squares problem solved by the fit functions: the residuals of the trajectory
+/// starting from a state against observations of an [`ObservationModel`].
+///
+/// Implements `LeastSquaresProblem<f64, Dyn, U4>`, so it can be handed to
+/// [`LevenbergMarquardt::minimize`] directly:
+/// - the four parameters are `(pos.x, pos.y, vel.x, vel.y)` of the initial state, mapped
+///   when bounded (see [`OptimizationProblem::with_bounds`]);
+/// - there is one residual per observation component, divided by its sigma if any;
+/// - the Jacobian is computed by forward mode automatic differentiation through the
+///   integrator.
+///
+/// The scalar type `F` of the solver defaults to `f64`, see [`fit_trajectory_with_precision`],
+/// and the force model `D` to the one of the configuration, see
+/// [`OptimizationProblem::with_dynamics`].
+pub struct OptimizationProblem<'a, M: ObservationModel, F = f64, D = IntegratorConfig> {
+    p: State<F>,
+    observed: &'a [M::Observation],
+    sigmas: Option<&'a [f64]>,
+    model: &'a M,
+    config: &'a IntegratorConfig,
+    dynamics: &'a D,
+    /// Fixed parameters are left untouched by `set_params` and get a zero Jacobian column.
+    free: [bool; 4],
+    /// The solver sees the parameters mapped by [`to_internal`].
+    bounds: [(f64, f64); 4],
+    /// Residuals at `p` with their derivatives, shared by `residuals()` and `jacobian()` so the
+    /// trajectory is integrated once per parameter set.
+    dual_residuals: OnceCell<Vec<Differential<F, Vector4<F>>>>,
+    jacobian_mode: JacobianMode,
+}
+
+impl<'a, M: ObservationModel, F> OptimizationProblem<'a, M, F>
+where
+    F: nalgebra::RealField + num_traits::Float + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    Differential<F, Vector4<F>>: Real + Debug + AddAssign + DivAssign + MulAssign + From<F> + 'static,
+{
+    /// Problem starting from `initial_guess`, with unit sigmas, all parameters free and unbounded.
+    pub fn new(initial_guess: State<F>, observed: &'a [M::Observation], model: &'a M, config: &'a IntegratorConfig) -> Self {
+        Self {
+            p: initial_guess,
+            observed,
+            sigmas: None,
+            model,
+            config,
+            dynamics: config,
+            free: [true; 4],
+            bounds: [(f64::NEG_INFINITY, f64::INFINITY); 4],
+            dual_residuals: OnceCell::new(),
+            jacobian_mode: JacobianMode::default(),
+        }
+    }
+}
+
+impl<'a, M: ObservationModel, F, D> OptimizationProblem<'a, M, F, D>
+where
+    F: nalgebra::RealField + num_traits::Float + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    Differential<F, Vector4<F>>: Real + Debug + AddAssign + DivAssign + MulAssign + From<F> + 'static,
+    D: Dynamics<F> + Dynamics<Differential<F, Vector4<F>>>,
+{
+    /// Integrates the trajectory under `dynamics` instead of the force model of the
+    /// configuration, which still provides the integrator and the sample grid.
+    pub fn with_dynamics<D2>(self, dynamics: &'a D2) -> OptimizationProblem<'a, M, F, D2>
+    where
+        D2: Dynamics<F> + Dynamics<Differential<F, Vector4<F>>>,
+    {
+        OptimizationProblem {
+            p: self.p,
+            observed: self.observed,
+            sigmas: self.sigmas,
+            model: self.model,
+            config: self.config,
+            dynamics,
+            free: self.free,
+            bounds: self.bounds,
+            dual_residuals: OnceCell::new(),
+            jacobian_mode: self.jacobian_mode,
+        }
+    }
+
+    /// Divides the residuals of observation `i` by `sigmas[i]`.
+    pub fn with_sigmas(self, sigmas: &'a [f64]) -> Self {
+        Self { sigmas: Some(sigmas), dual_residuals: OnceCell::new(), ..self }
+    }
+
+    /// See [`FitConfig::free_parameters`].
+    pub fn with_free_parameters(self, free: [bool; 4]) -> Self {
+        Self { free, dual_residuals: OnceCell::new(), ..self }
+    }
+
+    /// See [`FitConfig::bounds`]. Parameters on or outside their bounds (e.g. in the initial
+    /// guess) are moved slightly inside, as seen by the solver.
+    pub fn with_bounds(self, bounds: [(f64, f64); 4]) -> Self {
+        let params: Vector4<F> = Vector4::from(&self.p).map_with_location(|i, _, p| cast(to_external(bounds[i], to_internal(bounds[i], cast(p)))));
+        Self { p: params.into(), bounds, dual_residuals: OnceCell::new(), ..self }
+    }
+
+    /// See [`JacobianMode`].
+    pub fn with_jacobian_mode(self, jacobian_mode: JacobianMode) -> Self {
+        Self { jacobian_mode, ..self }
+    }
+
+    /// Current state, i.e. the solution after [`LevenbergMarquardt::minimize`].
+    pub fn state(&self) -> &State<F> {
+        &self.p
+    }
+
     fn residuals<T>(&self, initial_state: &State<T>) -> Vec<T>
     where
         T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+        D: Dynamics<T>,
     {
-        let sampled_trajectory = sampled_trajectory(&initial_state).collect::<Vec<_>>();
-        let predicted = observe(&sampled_trajectory).collect::<Vec<_>>();
-        self.observed.iter().zip(predicted.iter()).map(|(o, p)| T::from(*o).unwrap() - *p).collect::<Vec<_>>()
+        model_residuals(initial_state, self.observed, self.sigmas, self.model, self.dynamics, self.config)
+    }
+
+    fn dual_residuals(&self) -> &[Differential<F, Vector4<F>>] {
+        self.dual_residuals.get_or_init(|| self.residuals(&self.dual_state()))
+    }
+
+    /// The current state, seeded with unit derivatives for the free parameters.
+    fn dual_state(&self) -> State<Differential<F, Vector4<F>>> {
+        let mut params = [self.p.pos[0], self.p.pos[1], self.p.vel[0], self.p.vel[1]].map(Differential::<F, Vector4<F>>::from);
+        for (i, param) in params.iter_mut().enumerate() {
+            if self.free[i] {
+                param.derivative[i] = F::one();
+            }
+        }
+        State {
+            pos: Vector2::new(params[0], params[1]),
+            vel: Vector2::new(params[2], params[3]),
+        }
+    }
+
+    /// Jacobian with respect to the external parameters by finite differences, with the given
+    /// `step` or, if `None`, with steps relative to the parameter magnitudes.
+    fn finite_difference_jacobian(&self, central: bool, step: Option<F>) -> OMatrix<F, Dyn, U4> {
+        let params = Vector4::from(&self.p);
+        let residuals_at = |params: Vector4<F>| self.residuals::<F>(&State::from(params));
+        let base = residuals_at(params);
+        let epsilon = <F as num_traits::Float>::epsilon();
+        let relative_step = if central { num_traits::Float::cbrt(epsilon) } else { num_traits::Float::sqrt(epsilon) };
+        let mut jacobian = OMatrix::<F, Dyn, U4>::zeros_generic(Dyn(base.len()), U4::name());
+        for j in (0..4).filter(|&j| self.free[j]) {
+            let mut delta = Vector4::zeros();
+            delta[j] = step.unwrap_or_else(|| relative_step * (F::one() + num_traits::Float::abs(params[j])));
+            let plus = residuals_at(params + delta);
+            let (minus, width) = if central { (residuals_at(params - delta), delta[j] + delta[j]) } else { (base.clone(), delta[j]) };
+            for (i, (plus, minus)) in plus.into_iter().zip(minus).enumerate() {
+                jacobian[(i, j)] = (plus - minus) / width;
+            }
+        }
+        jacobian
+    }
+}
+
+impl<'a, M: ObservationModel> OptimizationProblem<'a, M> {
+    /// Norm of the unweighted residuals of each observation.
+    fn residual_norms(&self, state: &State<f64>) -> Vec<f64> {
+        let mut residuals = Vec::new();
+        self.observed.iter().zip(model_positions(state, self.observed, self.model, self.dynamics, self.config)).map(|(observed, pos)| {
+            residuals.clear();
+            self.model.residuals(observed, &pos, &mut residuals);
+            residuals.iter().map(|r| r * r).sum::<f64>().sqrt()
+        }).collect()
+    }
+}
+
+impl<'a, M: ObservationModel, F, D> LeastSquaresProblem<F, Dyn, U4> for OptimizationProblem<'a, M, F, D>
+where
+    F: nalgebra::RealField + num_traits::Float + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    Differential<F, Vector4<F>>: Real + Debug + AddAssign + DivAssign + MulAssign + From<F> + 'static,
+    D: Dynamics<F> + Dynamics<Differential<F, Vector4<F>>>,
+{
+    type ResidualStorage = nalgebra::storage::Owned<F, Dyn>;
+    type JacobianStorage = nalgebra::storage::Owned<F, Dyn, U4>;
+    type ParameterStorage = nalgebra::storage::Owned<F, U4>;
+    fn set_params(&mut self, x: &nalgebra::Vector<F, U4, Self::ParameterStorage>) {
+        let mut params = Vector4::from(&self.p);
+        for (i, free) in self.free.iter().enumerate() {
+            if *free {
+                params[i] = cast(to_external(self.bounds[i], cast(x[i])));
+            }
+        }
+        self.p = params.into();
+        self.dual_residuals = OnceCell::new();
+    }
+    fn params(&self) -> nalgebra::Vector<F, U4, Self::ParameterStorage> {
+        let params = Vector4::from(&self.p);
+        nalgebra::Vector::<F, U4, Self::ParameterStorage>::from_fn(|i, _| cast(to_internal(self.bounds[i], cast(params[i]))))
+    }
+    fn residuals(&self) -> Option<nalgebra::Vector<F, Dyn, Self::ResidualStorage>> {
+        let residuals: Vec<F> = match self.jacobian_mode {
+            // the derivatives are reused by `jacobian()`
+            JacobianMode::Autodiff => self.dual_residuals().iter().map(|r| num_traits::cast::<_, F>(*r).unwrap_or_else(<F as num_traits::Float>::nan)).collect(),
+            // no need to carry derivatives through the integrator
+            JacobianMode::ForwardDiff | JacobianMode::CentralDiff | JacobianMode::KeplerStm => self.residuals(&self.p),
+        };
+        let residuals = residuals.into_iter()
+            .map(|r| if num_traits::Float::is_finite(r) { r } else { cast(NON_FINITE_PENALTY) })
+            .collect();
+        Some(nalgebra::Vector::<F, Dyn, Self::ResidualStorage>::from_vec(residuals))
+    }
+    fn jacobian(&self) -> Option<nalgebra::Matrix<F, Dyn, U4, Self::JacobianStorage>> {
+        let mut jacobian = match self.jacobian_mode {
+            JacobianMode::Autodiff => {
+                let residuals = self.dual_residuals();
+                nalgebra::Matrix::<F, Dyn, U4, Self::JacobianStorage>::from_fn_generic(Dyn(residuals.len()), U4::name(), |i, j| residuals[i].derivative[j])
+            }
+            JacobianMode::ForwardDiff => self.finite_difference_jacobian(false, None),
+            JacobianMode::CentralDiff => self.finite_difference_jacobian(true, None),
+            JacobianMode::KeplerStm => {
+                let kepler = IntegratorConfig { integrator: Integrator::Kepler, ..self.config.clone() };
+                let residuals = model_residuals(&self.dual_state(), self.observed, self.sigmas, self.model, &kepler, &kepler);
+                nalgebra::Matrix::<F, Dyn, U4, Self::JacobianStorage>::from_fn_generic(Dyn(residuals.len()), U4::name(), |i, j| residuals[i].derivative[j])
+            }
+        };
+        // a free parameter with no effect on any residual (e.g. all the samples coincide after a
+        // bad step) makes `JᵀJ` singular, stop instead of letting the solver stall; checked before
+        // the bound scaling, which may underflow near a bound without the model being degenerate
+        let degenerate = jacobian.column_iter().zip(self.free).any(|(column, free)| free && column.iter().all(|&j| j == F::zero()));
+        let scale = Vector4::from(&self.p).map_with_location(|i, _, p| cast::<f64, F>(external_derivative(self.bounds[i], cast(p))));
+        for (j, mut column) in jacobian.column_iter_mut().enumerate() {
+            column *= scale[j];
+        }
+        (!degenerate && jacobian.iter().all(|j| num_traits::Float::is_finite(*j))).then_some(jacobian)
+    }
+}
+
+/// Forwards to an [`OptimizationProblem`], reporting every residual evaluation to `callback`.
+///
+/// The solver only asks for the Jacobian at the parameters it accepts, so each evaluation is
+/// held back until the next call tells whether it was accepted (a Jacobian) or rejected (new
+/// residuals).
+struct LoggedProblem<'a, 'b, M: ObservationModel> {
+    problem: OptimizationProblem<'a, M>,
+    callback: RefCell<&'b mut dyn FnMut(&State<f64>, f64, bool)>,
+    /// State and cost of the last evaluation, not reported yet.
+    pending: RefCell<Option<(State<f64>, f64)>>,
+}
+
+impl<'a, 'b, M: ObservationModel> LoggedProblem<'a, 'b, M> {
+    fn report_pending(&self, accepted: bool) {
+        if let Some((state, cost)) = self.pending.take() {
+            (self.callback.borrow_mut())(&state, cost, accepted);
+        }
     }
 }
 
-impl<'a> LeastSquaresProblem<f64, Dyn, U4> for OptimizationProblem<'a> {
+impl<'a, 'b, M: ObservationModel> LeastSquaresProblem<f64, Dyn, U4> for LoggedProblem<'a, 'b, M> {
     type ResidualStorage = nalgebra::storage::Owned<f64, Dyn>;
     type JacobianStorage = nalgebra::storage::Owned<f64, Dyn, U4>;
     type ParameterStorage = nalgebra::storage::Owned<f64, U4>;
     fn set_params(&mut self, x: &nalgebra::Vector<f64, U4, Self::ParameterStorage>) {
-        self.p.pos[0] = x[0];
-        self.p.pos[1] = x[1];
-        self.p.vel[0] = x[2];
-        self.p.vel[1] = x[3];
+        self.problem.set_params(x);
     }
     fn params(&self) -> nalgebra::Vector<f64, U4, Self::ParameterStorage> {
-        nalgebra::Vector::<f64, U4, Self::ParameterStorage>::new(
-            self.p.pos[0],
-            self.p.pos[1],
-            self.p.vel[0],
-            self.p.vel[1],
-        )
+        self.problem.params()
     }
     fn residuals(&self) -> Option<nalgebra::Vector<f64, Dyn, Self::ResidualStorage>> {
-        Some(nalgebra::Vector::<f64, Dyn, Self::ResidualStorage>::from_vec(self.residuals(&self.p)))
+        self.report_pending(false);
+        let residuals = LeastSquaresProblem::residuals(&self.problem)?;
+        *self.pending.borrow_mut() = Some((self.problem.state().clone(), 0.5 * residuals.norm_squared()));
+        Some(residuals)
     }
     fn jacobian(&self) -> Option<nalgebra::Matrix<f64, Dyn, U4, Self::JacobianStorage>> {
-        let mut state = State::<Differential<f64, Vector4<f64>>> {
-            pos: Vector2::new(
-                self.p.pos[0].into(),
-                self.p.pos[1].into(),
-            ),
-            vel: Vector2::new(
-                self.p.vel[0].into(),
-                self.p.vel[1].into(),
-            ),
-        };
-        state.pos[0].derivative[0] = 1.0;
-        state.pos[1].derivative[1] = 1.0;
-        state.vel[0].derivative[2] = 1.0;
-        state.vel[1].derivative[3] = 1.0;
-        let residuals = self.residuals(&state);
-        let mut jacobian = nalgebra::Matrix::<f64, Dyn, U4, Self::JacobianStorage>::zeros_generic(Dyn(residuals.len()), U4::name());
-        for (i, r) in residuals.iter().enumerate() {
-            jacobian[(i, 0)] = r.derivative[0];
-            jacobian[(i, 1)] = r.derivative[1];
-            jacobian[(i, 2)] = r.derivative[2];
-            jacobian[(i, 3)] = r.derivative[3];
-        }
-        Some(jacobian)
+        self.report_pending(true);
+        self.problem.jacobian()
+    }
+}
+
+/// Conversion between scalar types, for values known to be representable.
+fn cast<A: ToPrimitive, B: NumCast>(value: A) -> B {
+    B::from(value).unwrap()
+}
+
+/// Maps a parameter within `(lower, upper)` to the unbounded value seen by the solver,
+/// clamping values on or outside the bounds slightly inside.
+fn to_internal((lower, upper): (f64, f64), value: f64) -> f64 {
+    match (lower.is_finite(), upper.is_finite()) {
+        (true, true) => {
+            let margin = 1e-9 * (upper - lower);
+            let value = value.clamp(lower + margin, upper - margin);
+            ((value - lower) / (upper - value)).ln()
+        }
+        (true, false) => (value - lower).max(1e-9 * (1.0 + lower.abs())).ln(),
+        (false, true) => (upper - value).max(1e-9 * (1.0 + upper.abs())).ln(),
+        (false, false) => value,
+    }
+}
+
+/// Inverse of [`to_internal`].
+fn to_external((lower, upper): (f64, f64), internal: f64) -> f64 {
+    match (lower.is_finite(), upper.is_finite()) {
+        (true, true) => lower + (upper - lower) / (1.0 + (-internal).exp()),
+        (true, false) => lower + internal.exp(),
+        (false, true) => upper - internal.exp(),
+        (false, false) => internal,
+    }
+}
+
+/// Derivative of [`to_external`], expressed in terms of the external `value`.
+fn external_derivative((lower, upper): (f64, f64), value: f64) -> f64 {
+    match (lower.is_finite(), upper.is_finite()) {
+        (true, true) => (value - lower) * (upper - value) / (upper - lower),
+        (true, false) => value - lower,
+        (false, true) => -(upper - value),
+        (false, false) => 1.0,
     }
 }
\ No newline at end of file
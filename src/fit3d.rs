@@ -0,0 +1,209 @@
+use super::*;
+
+/// 3D counterpart of [`State`], for inclined orbits.
+#[derive(Debug, Clone)]
+pub struct State3<T = f64> {
+    pub pos: Vector3<T>,
+    pub vel: Vector3<T>,
+}
+
+pub fn acceleration_3d<T>(pos: &Vector3<T>, mu: T, epsilon: T) -> Vector3<T>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let dist2 = pos[0].powi(2) + pos[1].powi(2) + pos[2].powi(2) + epsilon.powi(2);
+    -pos * mu / dist2.sqrt().powi(3)
+}
+
+/// Oblateness perturbation of a central body with zonal harmonic `j2` and equatorial radius
+/// `re`, its pole being the z axis.
+pub fn j2_acceleration<T>(pos: &Vector3<T>, mu: T, j2: T, re: T) -> Vector3<T>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let r2 = pos[0].powi(2) + pos[1].powi(2) + pos[2].powi(2);
+    let z2_over_r2 = pos[2].powi(2) / r2;
+    let five = T::from(5.0).unwrap();
+    let factor = -T::from(1.5).unwrap() * j2 * mu * re.powi(2) / r2.sqrt().powi(5);
+    Vector3::new(
+        factor * pos[0] * (T::one() - five * z2_over_r2),
+        factor * pos[1] * (T::one() - five * z2_over_r2),
+        factor * pos[2] * (T::from(3.0).unwrap() - five * z2_over_r2),
+    )
+}
+
+/// 3D counterpart of [`integrate_trajectory`].
+///
+/// [`Integrator::Kepler`] and [`Integrator::Rkf45`] are not available in 3D and fall back to RK4.
+pub fn integrate_trajectory_3d<T>(initial_state: &State3<T>, config: &IntegratorConfig) -> impl Iterator<Item = State3<T>>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let step = match config.integrator {
+        Integrator::Euler => euler_step::<T, Vector3<T>> as fn(&mut Vector3<T>, &mut Vector3<T>, T, &dyn Fn(&Vector3<T>, &Vector3<T>) -> Vector3<T>),
+        Integrator::SymplecticEuler => symplectic_euler_step::<T, Vector3<T>>,
+        Integrator::Rk4 | Integrator::Kepler | Integrator::Rkf45 => rk4_step::<T, Vector3<T>>,
+    };
+    let mut state = initial_state.clone();
+    let dt = T::from(config.dt).unwrap();
+    let mu = T::from(config.mu).unwrap();
+    let epsilon = T::from(config.epsilon).unwrap();
+    let k = T::from(config.drag).unwrap();
+    let with_drag = config.drag != 0.0;
+    let j2 = T::from(config.j2).unwrap();
+    let re = T::from(config.equatorial_radius).unwrap();
+    let with_j2 = config.j2 != 0.0;
+    let acc = move |pos: &Vector3<T>, vel: &Vector3<T>| {
+        let mut acc = acceleration_3d(pos, mu, epsilon);
+        if with_drag {
+            acc += drag_acceleration(vel, k);
+        }
+        if with_j2 {
+            acc += j2_acceleration(pos, mu, j2, re);
+        }
+        acc
+    };
+    core::iter::from_fn(move || {
+        step(&mut state.pos, &mut state.vel, dt, &acc);
+        Some(state.clone())
+    }).take(config.steps)
+}
+
+pub fn sampled_trajectory_3d<T>(initial_state: &State3<T>, config: &IntegratorConfig) -> impl Iterator<Item = Vector3<T>>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    integrate_trajectory_3d(initial_state, config).step_by(config.stride.max(1)).map(|s| s.pos)
+}
+
+/// Azimuth (angle from the x axis in the xy plane) and elevation (angle above the xy plane).
+pub fn observe_az_el<'a, T>(sampled_trajectory: &'a [Vector3<T>]) -> impl Iterator<Item = (T, T)> + 'a
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    sampled_trajectory.iter().map(az_el)
+}
+
+fn az_el<T>(pos: &Vector3<T>) -> (T, T)
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    (pos[1].atan2(pos[0]), pos[2].atan2((pos[0].powi(2) + pos[1].powi(2)).sqrt()))
+}
+
+/// Fits a 3D initial state against `(azimuth, elevation)` observations.
+///
+/// Fails with [`FitError::Unsupported`] for the settings only implemented in 2D: fixed
+/// parameters, bounds, other Jacobian modes, an epoch offset, a moved central body, a rotating
+/// frame, extra bodies, and the [`Integrator::Kepler`] and [`Integrator::Rkf45`] integrators.
+pub fn fit_trajectory_3d(observations: &[(f64, f64)], config: &FitConfig) -> Result<(State3<f64>, MinimizationReport<f64>), FitError> {
+    if observations.len() < 2 {
+        return Err(FitError::TooFewObservations(observations.len()));
+    }
+    if observations.len() != config.integration.num_samples() {
+        return Err(FitError::ObservationCountMismatch { observations: observations.len(), samples: config.integration.num_samples() });
+    }
+    let defaults = FitConfig::default();
+    let unsupported = [
+        (config.free_parameters != defaults.free_parameters, "free_parameters"),
+        (config.bounds != defaults.bounds, "bounds"),
+        (config.jacobian != defaults.jacobian, "jacobian"),
+        (config.integration.epoch_offset != 0.0, "epoch_offset"),
+        (config.integration.center != Vector2::zeros(), "center"),
+        (config.integration.omega != 0.0, "omega"),
+        (!config.integration.bodies.is_empty(), "bodies"),
+        (matches!(config.integration.integrator, Integrator::Kepler | Integrator::Rkf45), "integrator"),
+    ];
+    if let Some((_, setting)) = unsupported.into_iter().find(|(set, _)| *set) {
+        return Err(FitError::Unsupported(setting));
+    }
+    config.validate_solver()?;
+    let direction = |(azimuth, elevation): (f64, f64)| Vector3::new(
+        elevation.cos() * azimuth.cos(),
+        elevation.cos() * azimuth.sin(),
+        elevation.sin(),
+    );
+    let initial_guess = State3 {
+        pos: direction(observations[0]),
+        vel: (direction(observations[1]) - direction(observations[0])) / config.integration.dt,
+    };
+    let problem = OptimizationProblem3 {
+        p: initial_guess,
+        observed: observations,
+        config: &config.integration,
+    };
+    let (result, report) = config.solver().minimize(problem);
+    Ok((result.p, report))
+}
+
+struct OptimizationProblem3<'a> {
+    p: State3<f64>,
+    observed: &'a [(f64, f64)],
+    config: &'a IntegratorConfig,
+}
+
+impl<'a> OptimizationProblem3<'a> {
+    fn residuals<T>(&self, initial_state: &State3<T>) -> Vec<T>
+    where
+        T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    {
+        let mut residuals = Vec::with_capacity(2 * self.observed.len());
+        for ((azimuth, elevation), pos) in self.observed.iter().zip(sampled_trajectory_3d(initial_state, self.config)) {
+            let (predicted_azimuth, predicted_elevation) = az_el(&pos);
+            residuals.push(wrap_angle(T::from(*azimuth).unwrap() - predicted_azimuth));
+            residuals.push(T::from(*elevation).unwrap() - predicted_elevation);
+        }
+        residuals
+    }
+}
+
+impl<'a> LeastSquaresProblem<f64, Dyn, U6> for OptimizationProblem3<'a> {
+    type ResidualStorage = nalgebra::storage::Owned<f64, Dyn>;
+    type JacobianStorage = nalgebra::storage::Owned<f64, Dyn, U6>;
+    type ParameterStorage = nalgebra::storage::Owned<f64, U6>;
+    fn set_params(&mut self, x: &nalgebra::Vector<f64, U6, Self::ParameterStorage>) {
+        for i in 0..3 {
+            self.p.pos[i] = x[i];
+            self.p.vel[i] = x[i + 3];
+        }
+    }
+    fn params(&self) -> nalgebra::Vector<f64, U6, Self::ParameterStorage> {
+        nalgebra::Vector::<f64, U6, Self::ParameterStorage>::new(
+            self.p.pos[0],
+            self.p.pos[1],
+            self.p.pos[2],
+            self.p.vel[0],
+            self.p.vel[1],
+            self.p.vel[2],
+        )
+    }
+    fn residuals(&self) -> Option<nalgebra::Vector<f64, Dyn, Self::ResidualStorage>> {
+        Some(nalgebra::Vector::<f64, Dyn, Self::ResidualStorage>::from_vec(self.residuals(&self.p)))
+    }
+    fn jacobian(&self) -> Option<nalgebra::Matrix<f64, Dyn, U6, Self::JacobianStorage>> {
+        let mut state = State3::<Differential<f64, Vector6<f64>>> {
+            pos: Vector3::new(
+                self.p.pos[0].into(),
+                self.p.pos[1].into(),
+                self.p.pos[2].into(),
+            ),
+            vel: Vector3::new(
+                self.p.vel[0].into(),
+                self.p.vel[1].into(),
+                self.p.vel[2].into(),
+            ),
+        };
+        for i in 0..3 {
+            state.pos[i].derivative[i] = 1.0;
+            state.vel[i].derivative[i + 3] = 1.0;
+        }
+        let residuals = self.residuals(&state);
+        let mut jacobian = nalgebra::Matrix::<f64, Dyn, U6, Self::JacobianStorage>::zeros_generic(Dyn(residuals.len()), U6::name());
+        for (i, r) in residuals.iter().enumerate() {
+            for j in 0..6 {
+                jacobian[(i, j)] = r.derivative[j];
+            }
+        }
+        Some(jacobian)
+    }
+}
@@ -0,0 +1,298 @@
+use core::f64::consts::PI;
+
+use super::*;
+
+/// Planar Keplerian elements.
+///
+/// Angles are measured counter-clockwise from the x axis; clockwise (retrograde) orbits
+/// are described by the elements of their mirror image across the x axis.
+#[derive(Debug, Clone)]
+pub struct KeplerianElements<T = f64> {
+    pub semi_major_axis: T,
+    pub eccentricity: T,
+    pub argument_of_periapsis: T,
+    pub true_anomaly: T,
+    pub retrograde: bool,
+}
+
+impl<T> KeplerianElements<T>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    /// The semi-major axis is negative for hyperbolic orbits and infinite (or huge) for parabolic
+    /// ones, see [`conic`].
+    pub fn from_state(state: &State<T>, mu: T) -> Self {
+        let retrograde = state.pos[0] * state.vel[1] - state.pos[1] * state.vel[0] < T::zero();
+        let state = if retrograde { mirror(state) } else { state.clone() };
+        let (x, y, vx, vy) = (state.pos[0], state.pos[1], state.vel[0], state.vel[1]);
+        let r = (x.powi(2) + y.powi(2)).sqrt();
+        let v2 = vx.powi(2) + vy.powi(2);
+        let r_dot_v = x * vx + y * vy;
+        let ex = ((v2 - mu / r) * x - r_dot_v * vx) / mu;
+        let ey = ((v2 - mu / r) * y - r_dot_v * vy) / mu;
+        let argument_of_periapsis = ey.atan2(ex);
+        Self {
+            semi_major_axis: (T::from(2.0).unwrap() / r - v2 / mu).recip(),
+            eccentricity: (ex.powi(2) + ey.powi(2)).sqrt(),
+            argument_of_periapsis,
+            true_anomaly: wrap_angle(y.atan2(x) - argument_of_periapsis),
+            retrograde,
+        }
+    }
+
+    pub fn to_state(&self, mu: T) -> State<T> {
+        let one = T::one();
+        let semi_latus_rectum = self.semi_major_axis * (one - self.eccentricity.powi(2));
+        let (sin_nu, cos_nu) = self.true_anomaly.sin_cos();
+        let r = semi_latus_rectum / (one + self.eccentricity * cos_nu);
+        let (sin_theta, cos_theta) = (self.argument_of_periapsis + self.true_anomaly).sin_cos();
+        let v_scale = (mu / semi_latus_rectum).sqrt();
+        let v_radial = v_scale * self.eccentricity * sin_nu;
+        let v_transverse = v_scale * (one + self.eccentricity * cos_nu);
+        let state = State {
+            pos: Vector2::new(r * cos_theta, r * sin_theta),
+            vel: Vector2::new(
+                v_radial * cos_theta - v_transverse * sin_theta,
+                v_radial * sin_theta + v_transverse * cos_theta,
+            ),
+        };
+        if self.retrograde { mirror(&state) } else { state }
+    }
+
+    /// Advances the true anomaly by a time `t` along the (elliptic) orbit, see [`conic`] to
+    /// check that it is.
+    pub fn propagate(&self, t: T, mu: T) -> Self {
+        let one = T::one();
+        let two = T::from(2.0).unwrap();
+        let e = self.eccentricity;
+        let half_nu = self.true_anomaly / two;
+        let initial_eccentric_anomaly = two * ((one - e).sqrt() * half_nu.sin()).atan2((one + e).sqrt() * half_nu.cos());
+        let mean_motion = (mu / self.semi_major_axis.powi(3)).sqrt();
+        let mean_anomaly = wrap_angle(initial_eccentric_anomaly - e * initial_eccentric_anomaly.sin() + mean_motion * t);
+        let half_eccentric_anomaly = solve_kepler(mean_anomaly, e) / two;
+        Self {
+            true_anomaly: two * ((one + e).sqrt() * half_eccentric_anomaly.sin()).atan2((one - e).sqrt() * half_eccentric_anomaly.cos()),
+            ..self.clone()
+        }
+    }
+}
+
+/// Orbits whose eccentricity is within this distance of 1 are classified as parabolic.
+const PARABOLIC_TOLERANCE: f64 = 1e-8;
+
+/// Shape of a two-body orbit, with the parameters that are finite for its kind.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Conic {
+    /// Bound orbit, `0 <= eccentricity < 1`.
+    Elliptic { semi_major_axis: f64, eccentricity: f64 },
+    /// Escape orbit with zero energy, whose semi-major axis is infinite.
+    Parabolic { semi_latus_rectum: f64 },
+    /// Escape orbit, `eccentricity > 1`; the semi-major axis is given as a positive length.
+    Hyperbolic { semi_major_axis: f64, eccentricity: f64 },
+}
+
+/// Classifies the orbit of `state`, unlike [`KeplerianElements::from_state`] whose semi-major
+/// axis blows up near parabolic orbits.
+pub fn conic(state: &State<f64>, mu: f64) -> Conic {
+    let energy = specific_energy(state, mu);
+    let h2 = angular_momentum(state).powi(2);
+    let eccentricity = (1.0 + 2.0 * energy * h2 / mu.powi(2)).max(0.0).sqrt();
+    if (eccentricity - 1.0).abs() < PARABOLIC_TOLERANCE {
+        Conic::Parabolic { semi_latus_rectum: h2 / mu }
+    } else if energy < 0.0 {
+        Conic::Elliptic { semi_major_axis: -mu / (2.0 * energy), eccentricity }
+    } else {
+        Conic::Hyperbolic { semi_major_axis: mu / (2.0 * energy), eccentricity }
+    }
+}
+
+/// Specific orbital energy `v^2/2 - mu/r`, conserved along the exact two-body trajectory.
+pub fn specific_energy(state: &State<f64>, mu: f64) -> f64 {
+    0.5 * state.vel.norm_squared() - mu / state.pos.norm()
+}
+
+/// Specific angular momentum `x*vy - y*vx` (the z component), positive for
+/// counter-clockwise orbits.
+pub fn angular_momentum(state: &State<f64>) -> f64 {
+    state.pos[0] * state.vel[1] - state.pos[1] * state.vel[0]
+}
+
+/// Eccentricity (Laplace-Runge-Lenz) vector `(v × h) / mu - pos / |pos|`, pointing to the
+/// periapsis with the eccentricity as length, conserved along the exact two-body trajectory.
+pub fn eccentricity_vector(state: &State<f64>, mu: f64) -> Vector2<f64> {
+    let h = angular_momentum(state);
+    Vector2::new(state.vel[1] * h, -state.vel[0] * h) / mu - state.pos / state.pos.norm()
+}
+
+/// Periapsis and apoapsis radii, from the energy and angular momentum.
+///
+/// The apoapsis is infinite for unbound (parabolic or hyperbolic) orbits.
+pub fn apsides(state: &State<f64>, mu: f64) -> (f64, f64) {
+    let h2 = angular_momentum(state).powi(2);
+    let eccentricity = (1.0 + 2.0 * specific_energy(state, mu) * h2 / mu.powi(2)).max(0.0).sqrt();
+    let periapsis = h2 / (mu * (1.0 + eccentricity));
+    let apoapsis = if eccentricity < 1.0 { h2 / (mu * (1.0 - eccentricity)) } else { f64::INFINITY };
+    (periapsis, apoapsis)
+}
+
+/// Angle between the velocity and the local horizontal, `atan2(pos·vel, |pos×vel|)`: positive
+/// when moving away from the center, zero at the apsides.
+pub fn flight_path_angle(state: &State<f64>) -> f64 {
+    state.pos.dot(&state.vel).atan2(angular_momentum(state).abs())
+}
+
+/// Period `2π sqrt(a^3 / mu)` of a bound orbit, `None` if the energy is not negative.
+pub fn orbital_period(state: &State<f64>, mu: f64) -> Option<f64> {
+    let energy = specific_energy(state, mu);
+    if energy >= 0.0 {
+        return None;
+    }
+    let semi_major_axis = -mu / (2.0 * energy);
+    Some(2.0 * PI * (semi_major_axis.powi(3) / mu).sqrt())
+}
+
+impl IntegratorConfig {
+    /// Copy of this configuration whose `samples` steps cover one orbital period of `state`
+    /// about `center`, or `unbound_span` if the orbit is not bound.
+    pub fn covering_one_period(&self, state: &State<f64>, samples: usize, unbound_span: f64) -> Self {
+        let span = orbital_period(&translate(state, &-self.center), self.mu).unwrap_or(unbound_span);
+        Self { dt: span / samples as f64, steps: samples, ..self.clone() }
+    }
+}
+
+/// Integrates `samples_per_orbit` steps covering one orbit, see
+/// [`IntegratorConfig::covering_one_period`].
+pub fn integrate_one_period(initial: &State<f64>, samples_per_orbit: usize, unbound_span: f64, config: &IntegratorConfig) -> impl Iterator<Item = State<f64>> {
+    integrate_trajectory(initial, &config.covering_one_period(initial, samples_per_orbit, unbound_span))
+}
+
+/// Whether the orbit is counter-clockwise, i.e. has a positive [`angular_momentum`].
+///
+/// Angle-only data cannot tell an orbit from its mirror image of opposite sense, so fits
+/// may converge to either.
+pub fn is_prograde(state: &State<f64>) -> bool {
+    angular_momentum(state) > 0.0
+}
+
+/// True anomaly at each model sample (i.e. at each observation) of the trajectory starting at
+/// `state`, about `center` and measured in the sense of motion.
+///
+/// Samples spanning the periapsis (anomalies around 0) constrain the orbit much better than a
+/// short arc far from it.
+pub fn true_anomalies(state: &State<f64>, config: &IntegratorConfig) -> Vec<f64> {
+    integrate_trajectory(state, config)
+        .step_by(config.stride.max(1))
+        .map(|s| KeplerianElements::from_state(&translate(&s, &-config.center), config.mu).true_anomaly)
+        .collect()
+}
+
+/// `state` rotated about the origin so that the periapsis of its orbit lies along +x, a
+/// canonical representation for comparing fits. Circular orbits have no periapsis and are
+/// rotated so that the position lies along +x.
+pub fn normalize_state(state: &State<f64>, mu: f64) -> State<f64> {
+    let eccentricity = eccentricity_vector(state, mu);
+    let direction = if eccentricity.norm() > 1e-12 { eccentricity } else { state.pos };
+    let rotation = nalgebra::Rotation2::new(-direction[1].atan2(direction[0]));
+    State {
+        pos: rotation * state.pos,
+        vel: rotation * state.vel,
+    }
+}
+
+/// [`normalize_state`] also scaled to a unit semi-major axis and `mu = 1` (velocities are
+/// scaled by `sqrt(a / mu)`), `None` for unbound orbits.
+pub fn normalize_state_scaled(state: &State<f64>, mu: f64) -> Option<State<f64>> {
+    let energy = specific_energy(state, mu);
+    if energy >= 0.0 {
+        return None;
+    }
+    let semi_major_axis = -mu / (2.0 * energy);
+    let normalized = normalize_state(state, mu);
+    Some(State {
+        pos: normalized.pos / semi_major_axis,
+        vel: normalized.vel * (semi_major_axis / mu).sqrt(),
+    })
+}
+
+/// Relative change in [`specific_energy`] (about `center`) between `initial` and the last state
+/// of its integrated trajectory, a measure of the integrator error for the given `dt` and `steps`.
+pub fn energy_drift(initial: &State<f64>, config: &IntegratorConfig) -> f64 {
+    let energy = |state: &State<f64>| specific_energy(&translate(state, &-config.center), config.mu);
+    let initial_energy = energy(initial);
+    let last = integrate_trajectory(initial, config).last().unwrap_or_else(|| initial.clone());
+    (energy(&last) - initial_energy) / initial_energy.abs()
+}
+
+/// Solves Kepler's equation `E - e sin(E) = M` for the eccentric anomaly `E`.
+///
+/// Plain Newton iterations, so derivatives propagate through the solution when `T` is a
+/// `Differential`.
+pub fn solve_kepler<T>(mean_anomaly: T, eccentricity: T) -> T
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let mut eccentric_anomaly = if eccentricity < T::from(0.8).unwrap() {
+        mean_anomaly
+    } else {
+        T::from(PI).unwrap() * mean_anomaly.signum()
+    };
+    for _ in 0..50 {
+        let delta = (eccentric_anomaly - eccentricity * eccentric_anomaly.sin() - mean_anomaly)
+            / (T::one() - eccentricity * eccentric_anomaly.cos());
+        eccentric_anomaly = eccentric_anomaly - delta;
+        if delta.abs() < T::epsilon().sqrt() {
+            break;
+        }
+    }
+    eccentric_anomaly
+}
+
+/// Propagates a bound two-body state by a time `t` analytically.
+pub fn propagate_kepler<T>(initial: &State<T>, t: T, mu: T) -> State<T>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    KeplerianElements::from_state(initial, mu).propagate(t, mu).to_state(mu)
+}
+
+/// Same sampling as [`integrate_trajectory_euler`], but using [`propagate_kepler`].
+pub fn propagate_trajectory_kepler<T>(initial_state: &State<T>, config: &IntegratorConfig) -> impl Iterator<Item = State<T>>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let dt = T::from(config.dt).unwrap();
+    let mu = T::from(config.mu).unwrap();
+    let elements = KeplerianElements::from_state(&translate(initial_state, &-config.center), mu);
+    let center = config.center;
+    (1..=config.steps).map(move |i| translate(&elements.propagate(dt * T::from(i).unwrap(), mu).to_state(mu), &center))
+}
+
+/// Assumes `mu = 1`, use [`KeplerianElements::to_state`] for other values.
+impl<T> From<KeplerianElements<T>> for State<T>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    fn from(elements: KeplerianElements<T>) -> Self {
+        elements.to_state(T::one())
+    }
+}
+
+/// Assumes `mu = 1`, use [`KeplerianElements::from_state`] for other values.
+impl<T> From<State<T>> for KeplerianElements<T>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    fn from(state: State<T>) -> Self {
+        Self::from_state(&state, T::one())
+    }
+}
+
+fn mirror<T>(state: &State<T>) -> State<T>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    State {
+        pos: Vector2::new(state.pos[0], -state.pos[1]),
+        vel: Vector2::new(state.vel[0], -state.vel[1]),
+    }
+}
@@ -0,0 +1,44 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, format, string::{String, ToString}, vec, vec::Vec};
+use core::fmt::{Debug, Display};
+use core::ops::{DivAssign, MulAssign, AddAssign, Add, Mul};
+
+use differential::Differential;
+use levenberg_marquardt::{LeastSquaresProblem, LevenbergMarquardt, MinimizationReport, TerminationReason};
+use nalgebra::{Vector2, Vector3, U4, U6, Dyn, Vector4, Vector6, Matrix4, OMatrix, SVector, DimName, Scalar};
+// `Float` and `RealField` are named by path: without `std`, `f64` has no inherent math methods
+// and `x.sqrt()` would be ambiguous if they were in scope next to `Real`
+use num_traits::{real::Real, NumCast, ToPrimitive};
+#[cfg(feature = "std")]
+use rand::{rngs::StdRng, Rng, SeedableRng};
+#[cfg(feature = "std")]
+use rand_distr::StandardNormal;
+
+#[cfg(feature = "plot")]
+use plotters::prelude::*;
+
+mod fit; pub use fit::*;
+mod kepler; pub use kepler::*;
+mod observation; pub use observation::*;
+mod fit3d; pub use fit3d::*;
+#[cfg(feature = "std")]
+mod csv;
+#[cfg(feature = "std")]
+pub use csv::*;
+#[cfg(feature = "plot")]
+mod plot;
+#[cfg(feature = "plot")]
+pub use plot::*;
+#[cfg(feature = "std")]
+mod synthetic;
+#[cfg(feature = "std")]
+pub use synthetic::*;
+mod rkf45; pub use rkf45::*;
+mod builder; pub use builder::*;
+mod dynamics; pub use dynamics::*;
+mod error; pub use error::*;
+mod record; pub use record::*;
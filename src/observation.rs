@@ -0,0 +1,239 @@
+use super::*;
+
+/// Measurement function used by the fit.
+pub trait ObservationModel {
+    /// A single measurement, taken at one model sample (or time, see
+    /// [`ObservationModel::observation_times`]).
+    type Observation;
+
+    /// Appends the residuals `observed - predicted` of the sample at `pos` to `residuals`.
+    fn residuals<T>(&self, observed: &Self::Observation, pos: &Vector2<T>, residuals: &mut Vec<T>)
+    where
+        T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static;
+
+    /// Heuristic starting point for the fit, relative to [`IntegratorConfig::center`].
+    fn initial_guess(&self, observations: &[Self::Observation], config: &IntegratorConfig) -> State<f64>;
+
+    /// Times of the observations, or `None` (the default) if observation `i` is taken at
+    /// model sample `i`.
+    fn observation_times(&self, _observations: &[Self::Observation]) -> Option<Vec<f64>> {
+        None
+    }
+}
+
+/// Bearing (angle from the x axis) of the object, as produced by [`observe`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bearing;
+
+/// Distance of the object from the origin, as produced by [`observe_range`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Range;
+
+impl ObservationModel for Bearing {
+    type Observation = f64;
+
+    fn residuals<T>(&self, observed: &f64, pos: &Vector2<T>, residuals: &mut Vec<T>)
+    where
+        T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    {
+        residuals.push(wrap_angle(T::from(*observed).unwrap() - bearing(pos)));
+    }
+
+    /// Unit circle positions at the first two bearings, see [`bearing_initial_guess`].
+    fn initial_guess(&self, observations: &[f64], config: &IntegratorConfig) -> State<f64> {
+        bearing_initial_guess(observations, 1.0, config)
+    }
+}
+
+/// Positions at the first two bearings on a circle of the assumed `radius`, with the velocity
+/// between them.
+pub fn bearing_initial_guess(observations: &[f64], radius: f64, config: &IntegratorConfig) -> State<f64> {
+    let first = bearing_to_unit_vector(observations[0]) * radius;
+    let second = bearing_to_unit_vector(observations[1]) * radius;
+    State {
+        pos: first,
+        vel: (second - first) / config.dt,
+    }
+}
+
+impl ObservationModel for Range {
+    type Observation = f64;
+
+    fn residuals<T>(&self, observed: &f64, pos: &Vector2<T>, residuals: &mut Vec<T>)
+    where
+        T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    {
+        residuals.push(T::from(*observed).unwrap() - range(pos));
+    }
+
+    /// Object on the x axis with the observed radial velocity and a circular transverse one.
+    fn initial_guess(&self, observations: &[f64], config: &IntegratorConfig) -> State<f64> {
+        State {
+            pos: Vector2::new(observations[0], 0.0),
+            vel: Vector2::new((observations[1] - observations[0]) / config.dt, (config.mu / observations[0]).sqrt()),
+        }
+    }
+}
+
+/// Bearing and range measured together, as `(bearing, range)` pairs.
+///
+/// Each sample contributes two interleaved residuals `[bearing, range]`, scaled by the
+/// respective weights (typically `1 / sigma`) since the two have different units.
+#[derive(Debug, Clone, Copy)]
+pub struct BearingRange {
+    pub bearing_weight: f64,
+    pub range_weight: f64,
+}
+
+impl Default for BearingRange {
+    fn default() -> Self {
+        Self { bearing_weight: 1.0, range_weight: 1.0 }
+    }
+}
+
+impl ObservationModel for BearingRange {
+    type Observation = (f64, f64);
+
+    fn residuals<T>(&self, observed: &(f64, f64), pos: &Vector2<T>, residuals: &mut Vec<T>)
+    where
+        T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    {
+        Bearing.residuals(&observed.0, pos, residuals);
+        Range.residuals(&observed.1, pos, residuals);
+        let n = residuals.len();
+        residuals[n - 2] *= T::from(self.bearing_weight).unwrap();
+        residuals[n - 1] *= T::from(self.range_weight).unwrap();
+    }
+
+    /// Positions at the first two observations, with the velocity between them.
+    fn initial_guess(&self, observations: &[(f64, f64)], config: &IntegratorConfig) -> State<f64> {
+        let position = |(angle, range): (f64, f64)| bearing_to_unit_vector(angle) * range;
+        State {
+            pos: position(observations[0]),
+            vel: (position(observations[1]) - position(observations[0])) / config.dt,
+        }
+    }
+}
+
+/// Bearing with a time tag, as `(time, bearing)` pairs, for irregularly sampled data.
+///
+/// The model is evaluated at each observation time with [`states_at`] instead of on the
+/// `steps`/`stride` grid, so any number of observations can be fitted.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimedBearing;
+
+impl ObservationModel for TimedBearing {
+    type Observation = (f64, f64);
+
+    fn residuals<T>(&self, observed: &(f64, f64), pos: &Vector2<T>, residuals: &mut Vec<T>)
+    where
+        T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    {
+        Bearing.residuals(&observed.1, pos, residuals);
+    }
+
+    /// Unit circle positions at the first two bearings, as for [`Bearing`].
+    fn initial_guess(&self, observations: &[(f64, f64)], _config: &IntegratorConfig) -> State<f64> {
+        let (t0, bearing0) = observations[0];
+        let (t1, bearing1) = observations[1];
+        State {
+            pos: bearing_to_unit_vector(bearing0),
+            vel: (bearing_to_unit_vector(bearing1) - bearing_to_unit_vector(bearing0)) / (t1 - t0),
+        }
+    }
+
+    fn observation_times(&self, observations: &[(f64, f64)]) -> Option<Vec<f64>> {
+        Some(observations.iter().map(|(t, _)| *t).collect())
+    }
+}
+
+/// Cartesian position of the object, contributing two residuals `[x, y]` per sample.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Position;
+
+impl ObservationModel for Position {
+    type Observation = Vector2<f64>;
+
+    fn residuals<T>(&self, observed: &Vector2<f64>, pos: &Vector2<T>, residuals: &mut Vec<T>)
+    where
+        T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    {
+        residuals.push(T::from(observed[0]).unwrap() - pos[0]);
+        residuals.push(T::from(observed[1]).unwrap() - pos[1]);
+    }
+
+    fn initial_guess(&self, observations: &[Vector2<f64>], config: &IntegratorConfig) -> State<f64> {
+        State {
+            pos: observations[0],
+            vel: (observations[1] - observations[0]) / config.dt,
+        }
+    }
+}
+
+/// A single measurement of any kind, for datasets mixing them, see [`Mixed`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Observation {
+    Bearing(f64),
+    Range(f64),
+    Position(Vector2<f64>),
+}
+
+/// Heterogeneous observations: each [`Observation`] contributes the residuals of the model of
+/// its kind ([`Bearing`], [`Range`] or [`Position`]), all sharing the same propagation.
+///
+/// Since the residuals have different units, weighting them with sigmas is usually needed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mixed;
+
+impl ObservationModel for Mixed {
+    type Observation = Observation;
+
+    fn residuals<T>(&self, observed: &Observation, pos: &Vector2<T>, residuals: &mut Vec<T>)
+    where
+        T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+    {
+        match observed {
+            Observation::Bearing(angle) => Bearing.residuals(angle, pos, residuals),
+            Observation::Range(range) => Range.residuals(range, pos, residuals),
+            Observation::Position(position) => Position.residuals(position, pos, residuals),
+        }
+    }
+
+    /// Positions at the first two observations (on the unit circle for bearings, on the x axis
+    /// for ranges), with the velocity between them.
+    fn initial_guess(&self, observations: &[Observation], config: &IntegratorConfig) -> State<f64> {
+        let position = |observation: &Observation| match *observation {
+            Observation::Bearing(angle) => bearing_to_unit_vector(angle),
+            Observation::Range(range) => Vector2::new(range, 0.0),
+            Observation::Position(position) => position,
+        };
+        State {
+            pos: position(&observations[0]),
+            vel: (position(&observations[1]) - position(&observations[0])) / config.dt,
+        }
+    }
+}
+
+/// Unit vector pointing along the bearing `angle`.
+pub fn bearing_to_unit_vector(angle: f64) -> Vector2<f64> {
+    Vector2::new(angle.cos(), angle.sin())
+}
+
+/// Bearing of `v`, the inverse of [`bearing_to_unit_vector`] up to the length of `v`.
+pub fn bearing_of(v: &Vector2<f64>) -> f64 {
+    bearing(v)
+}
+
+pub(crate) fn bearing<T>(pos: &Vector2<T>) -> T
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    pos[1].atan2(pos[0])
+}
+
+pub(crate) fn range<T>(pos: &Vector2<T>) -> T
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    (pos[0].powi(2) + pos[1].powi(2)).sqrt()
+}
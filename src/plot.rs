@@ -0,0 +1,251 @@
+use std::error::Error;
+use std::path::Path;
+
+use plotters::coord::Shift;
+
+use super::*;
+
+const PLOT_SIZE: (u32, u32) = (500, 500);
+
+/// Renders the fit to `path`, as SVG or PNG depending on the file extension, see [`plot_fit`].
+pub fn render_fit(path: &str, actual: &State<f64>, sampled: &[Vector2<f64>], computed: &State<f64>, guess: Option<&State<f64>>, config: &IntegratorConfig) -> Result<(), Box<dyn Error>> {
+    match Path::new(path).extension().and_then(|extension| extension.to_str()) {
+        Some("svg") => {
+            let root = SVGBackend::new(path, PLOT_SIZE).into_drawing_area();
+            plot_fit(&root, actual, sampled, computed, guess, config)?;
+            root.present()?;
+        }
+        Some("png") => {
+            let root = BitMapBackend::new(path, PLOT_SIZE).into_drawing_area();
+            plot_fit(&root, actual, sampled, computed, guess, config)?;
+            root.present()?;
+        }
+        _ => return Err(format!("unsupported plot format: {}", path).into()),
+    }
+    Ok(())
+}
+
+/// Draws the central body, the actual trajectory, the observed positions and the computed
+/// trajectory, with a legend, on any drawing area.
+///
+/// If given, the trajectory of the initial `guess` of the fit is drawn dashed, which helps
+/// understanding a diverging fit.
+pub fn plot_fit<DB: DrawingBackend>(root: &DrawingArea<DB, Shift>, actual: &State<f64>, sampled: &[Vector2<f64>], computed: &State<f64>, guess: Option<&State<f64>>, config: &IntegratorConfig) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    root.fill(&WHITE)?;
+
+    let actual_points = integrate_trajectory(actual, config)
+        .map(|s| (s.pos[0], s.pos[1]))
+        .collect::<Vec<_>>();
+    let computed_points = integrate_trajectory(computed, config)
+        .map(|s| (s.pos[0], s.pos[1]))
+        .collect::<Vec<_>>();
+    let guess_points = guess.map_or_else(Vec::new, |guess| integrate_trajectory(guess, config)
+        .map(|s| (s.pos[0], s.pos[1]))
+        .collect::<Vec<_>>());
+    let (x_range, y_range) = plot_bounds(
+        actual_points.iter().copied()
+            .chain(sampled.iter().map(|p| (p[0], p[1])))
+            .chain(computed_points.iter().copied())
+            .chain(guess_points.iter().copied())
+            .chain(std::iter::once((config.center[0], config.center[1]))),
+    );
+
+    let mut chart = ChartBuilder::on(root)
+        .margin(10)
+        .caption(
+            "2D orbit fit example",
+            ("sans-serif", 20),
+        )
+        //.set_label_area_size(LabelAreaPosition::Left, 60)
+        //.set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .build_cartesian_2d(
+            x_range,
+            y_range,
+        )?;
+
+    chart
+        .configure_mesh()
+        .max_light_lines(4)
+        .draw()?;
+
+    chart.draw_series(
+        (0..1).map(|_| Circle::new((config.center[0], config.center[1]), 10, BLUE.filled())),
+    )?;
+
+    chart.draw_series(LineSeries::new(
+        actual_points,
+        &RED,
+    ))?
+    .label("actual trajectory")
+    .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &RED));
+
+    chart.draw_series(
+        sampled.iter().map(|p| Cross::new((p[0], p[1]), 3, BLACK)),
+    )?
+    .label("observations")
+    .legend(|(x, y)| Cross::new((x + 10, y), 5, &BLACK));
+
+    chart.draw_series(LineSeries::new(
+        computed_points,
+        &BLUE,
+    ))?
+    .label("computed trajectory")
+    .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &BLUE));
+
+    if !guess_points.is_empty() {
+        chart.draw_series(DashedLineSeries::new(
+            guess_points,
+            5,
+            5,
+            GREEN.into(),
+        ))?
+        .label("initial guess")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &GREEN));
+    }
+
+    chart.configure_series_labels()
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .position(SeriesLabelPosition::UpperLeft)
+        .draw()?;
+
+    Ok(())
+}
+
+/// Renders the bearing residuals of `state` against time to `path`, as SVG or PNG depending on
+/// the file extension.
+pub fn plot_residuals(path: &str, state: &State<f64>, observations: &[f64], config: &IntegratorConfig) -> Result<(), Box<dyn Error>> {
+    match Path::new(path).extension().and_then(|extension| extension.to_str()) {
+        Some("svg") => {
+            let root = SVGBackend::new(path, PLOT_SIZE).into_drawing_area();
+            draw_residuals(&root, state, observations, config)?;
+            root.present()?;
+        }
+        Some("png") => {
+            let root = BitMapBackend::new(path, PLOT_SIZE).into_drawing_area();
+            draw_residuals(&root, state, observations, config)?;
+            root.present()?;
+        }
+        _ => return Err(format!("unsupported plot format: {}", path).into()),
+    }
+    Ok(())
+}
+
+/// Draws the residuals (observed - predicted) at the sample times, with a zero reference line,
+/// on any drawing area.
+pub fn draw_residuals<DB: DrawingBackend>(root: &DrawingArea<DB, Shift>, state: &State<f64>, observations: &[f64], config: &IntegratorConfig) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    root.fill(&WHITE)?;
+
+    let sample_interval = config.dt * config.stride.max(1) as f64;
+    let points = compute_residuals(state, observations, config).into_iter()
+        .enumerate()
+        .map(|(i, r)| (config.epoch_offset + config.dt + i as f64 * sample_interval, r))
+        .collect::<Vec<_>>();
+    let t_max = points.last().map_or(config.dt, |p| p.0) + config.dt;
+    // symmetric around zero, residuals are usually much smaller than the `plot_bounds` minimum span
+    let r_max = 1.1 * points.iter().map(|p| p.1.abs()).fold(f64::MIN_POSITIVE, f64::max);
+    let (x_range, y_range) = (config.epoch_offset..t_max, -r_max..r_max);
+
+    let mut chart = ChartBuilder::on(root)
+        .margin(10)
+        .caption(
+            "bearing residuals",
+            ("sans-serif", 20),
+        )
+        .x_label_area_size(30)
+        .y_label_area_size(50)
+        .build_cartesian_2d(
+            x_range.clone(),
+            y_range,
+        )?;
+
+    chart
+        .configure_mesh()
+        .max_light_lines(4)
+        .x_desc("t")
+        .y_desc("residual (rad)")
+        .draw()?;
+
+    chart.draw_series(LineSeries::new(
+        [(x_range.start, 0.0), (x_range.end, 0.0)],
+        &BLACK,
+    ))?;
+
+    chart.draw_series(LineSeries::new(
+        points.iter().copied(),
+        &BLUE.mix(0.5),
+    ))?;
+
+    chart.draw_series(
+        points.iter().map(|&p| Circle::new(p, 3, BLUE.filled())),
+    )?;
+
+    Ok(())
+}
+
+/// Writes a GIF animation of the computed trajectory growing over `frames` frames, above the
+/// static central body and observed positions.
+pub fn animate_fit(path: &str, computed: &State<f64>, sampled: &[Vector2<f64>], frames: usize, config: &IntegratorConfig) -> Result<(), Box<dyn Error>> {
+    let points = integrate_trajectory(computed, config)
+        .map(|s| (s.pos[0], s.pos[1]))
+        .collect::<Vec<_>>();
+    let (x_range, y_range) = plot_bounds(
+        points.iter().copied()
+            .chain(sampled.iter().map(|p| (p[0], p[1])))
+            .chain(std::iter::once((config.center[0], config.center[1]))),
+    );
+
+    let root = BitMapBackend::gif(path, PLOT_SIZE, 100)?.into_drawing_area();
+    for frame in 1..=frames {
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(&root)
+            .margin(10)
+            .caption(
+                "2D orbit fit example",
+                ("sans-serif", 20),
+            )
+            .build_cartesian_2d(
+                x_range.clone(),
+                y_range.clone(),
+            )?;
+        chart
+            .configure_mesh()
+            .max_light_lines(4)
+            .draw()?;
+        chart.draw_series(
+            (0..1).map(|_| Circle::new((config.center[0], config.center[1]), 10, BLUE.filled())),
+        )?;
+        chart.draw_series(
+            sampled.iter().map(|p| Cross::new((p[0], p[1]), 3, BLACK)),
+        )?;
+        chart.draw_series(LineSeries::new(
+            points[..points.len() * frame / frames].iter().copied(),
+            &BLUE,
+        ))?;
+        root.present()?;
+    }
+    Ok(())
+}
+
+/// Bounding box of `points` with a 5% margin, padded to a minimum span for degenerate data.
+fn plot_bounds(points: impl Iterator<Item = (f64, f64)>) -> (std::ops::Range<f64>, std::ops::Range<f64>) {
+    const MIN_SPAN: f64 = 1.0;
+    let (mut x_min, mut x_max) = (f64::INFINITY, f64::NEG_INFINITY);
+    let (mut y_min, mut y_max) = (f64::INFINITY, f64::NEG_INFINITY);
+    for (x, y) in points {
+        x_min = x_min.min(x);
+        x_max = x_max.max(x);
+        y_min = y_min.min(y);
+        y_max = y_max.max(y);
+    }
+    let pad = |min: f64, max: f64| {
+        let span = max - min;
+        if span < MIN_SPAN {
+            let center = (min + max) / 2.0;
+            center - MIN_SPAN / 2.0..center + MIN_SPAN / 2.0
+        } else {
+            min - 0.05 * span..max + 0.05 * span
+        }
+    };
+    (pad(x_min, x_max), pad(y_min, y_max))
+}
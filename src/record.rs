@@ -0,0 +1,49 @@
+use super::*;
+
+/// Everything needed to archive and re-run a bearing fit: the inputs, the result and a summary
+/// of the solver report.
+///
+/// With the `serde` feature it can be serialized to any format, e.g. JSON with `serde_json`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FitRecord {
+    pub observations: Vec<f64>,
+    pub config: FitConfig,
+    pub state: State<f64>,
+    /// See [`covariance`], `None` if it could not be computed.
+    pub covariance: Option<Matrix4<f64>>,
+    /// The [`TerminationReason`] of the solver, formatted with `Debug`.
+    pub termination: String,
+    /// Number of residual evaluations of the solver.
+    pub evaluations: usize,
+    /// Final cost `0.5 * |r|^2`.
+    pub objective_function: f64,
+    /// Number of residuals.
+    pub n: usize,
+    /// Degrees of freedom, see [`FitConfig::degrees_of_freedom`] (`0` if not positive).
+    pub dof: usize,
+}
+
+impl FitRecord {
+    /// Runs [`fit_trajectory_with_covariance`] and records it.
+    pub fn new(observations: &[f64], config: &FitConfig) -> Result<Self, FitError> {
+        let (state, covariance, report) = fit_trajectory_with_covariance(observations, config)?;
+        let n = compute_residuals(&state, observations, &config.integration).len();
+        Ok(Self {
+            observations: observations.to_vec(),
+            config: config.clone(),
+            state,
+            covariance,
+            termination: format!("{:?}", report.termination),
+            evaluations: report.number_of_evaluations,
+            objective_function: report.objective_function,
+            n,
+            dof: config.degrees_of_freedom(n).unwrap_or(0),
+        })
+    }
+
+    /// Fits the recorded observations again with the recorded configuration.
+    pub fn rerun(&self) -> Result<Self, FitError> {
+        Self::new(&self.observations, &self.config)
+    }
+}
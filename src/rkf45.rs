@@ -0,0 +1,111 @@
+use super::*;
+
+const A: [[f64; 5]; 5] = [
+    [1.0 / 4.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 32.0, 9.0 / 32.0, 0.0, 0.0, 0.0],
+    [1932.0 / 2197.0, -7200.0 / 2197.0, 7296.0 / 2197.0, 0.0, 0.0],
+    [439.0 / 216.0, -8.0, 3680.0 / 513.0, -845.0 / 4104.0, 0.0],
+    [-8.0 / 27.0, 2.0, -3544.0 / 2565.0, 1859.0 / 4104.0, -11.0 / 40.0],
+];
+/// Weights of the 4th order solution, which is the one propagated.
+const B4: [f64; 6] = [25.0 / 216.0, 0.0, 1408.0 / 2565.0, 2197.0 / 4104.0, -1.0 / 5.0, 0.0];
+/// Weights of the 5th order solution, only used for the error estimate.
+const B5: [f64; 6] = [16.0 / 135.0, 0.0, 6656.0 / 12825.0, 28561.0 / 56430.0, -9.0 / 50.0, 2.0 / 55.0];
+
+/// Adaptive Runge-Kutta-Fehlberg 4(5) integration.
+///
+/// The step is adapted to keep the local error estimate below `config.tolerance` (relative to
+/// `1 + |y|`), and the trajectory is emitted at the same times as the fixed-step integrators,
+/// `i * dt` for `i` in `1..=steps`, by cubic Hermite interpolation between accepted steps.
+/// Step sizes are treated as constants, so derivatives propagate through the solution when `T`
+/// is a `Differential`.
+pub fn integrate_trajectory_rkf45<T>(initial_state: &State<T>, config: &IntegratorConfig) -> impl Iterator<Item = State<T>>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    rkf45_trajectory(initial_state, config, &total_acceleration(config)).into_iter()
+}
+
+/// [`integrate_trajectory_rkf45`] for any acceleration `acc(pos, vel)`.
+pub(crate) fn rkf45_trajectory<T>(initial_state: &State<T>, config: &IntegratorConfig, acc: &dyn Fn(&Vector2<T>, &Vector2<T>) -> Vector2<T>) -> Vec<State<T>>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    // `dt < 0` integrates backward in time, the step sizes below are magnitudes
+    let direction = config.dt.signum();
+    let min_step = config.dt.abs() * 1e-9;
+
+    let mut output = Vec::with_capacity(config.steps);
+    let mut state = initial_state.clone();
+    let (mut t, mut h) = (0.0, config.dt.abs());
+    while output.len() < config.steps {
+        let (next, error) = rkf45_step(&state, T::from(direction * h).unwrap(), acc);
+        let ratio = error / config.tolerance;
+        if ratio > 1.0 && h > min_step {
+            h = (h * (0.9 * ratio.powf(-0.25)).max(0.2)).max(min_step);
+            continue;
+        }
+        while output.len() < config.steps {
+            let t_out = config.dt.abs() * (output.len() + 1) as f64;
+            if t_out > t + h {
+                break;
+            }
+            output.push(hermite(&state, &next, (t_out - t) / h, T::from(direction * h).unwrap(), acc));
+        }
+        t += h;
+        state = next;
+        h *= (0.9 * ratio.max(1e-10).powf(-0.2)).min(5.0);
+    }
+    output
+}
+
+/// One Fehlberg step of size `h`, returning the new state and the scaled error estimate.
+fn rkf45_step<T>(state: &State<T>, h: T, acc: &dyn Fn(&Vector2<T>, &Vector2<T>) -> Vector2<T>) -> (State<T>, f64)
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let c = |x: f64| T::from(x).unwrap();
+    let mut k_pos = Vec::with_capacity(6);
+    let mut k_vel = Vec::with_capacity(6);
+    k_pos.push(state.vel);
+    k_vel.push(acc(&state.pos, &state.vel));
+    for a in A {
+        let mut pos = state.pos;
+        let mut vel = state.vel;
+        for (j, &a) in a.iter().enumerate().take(k_pos.len()) {
+            pos += k_pos[j] * (h * c(a));
+            vel += k_vel[j] * (h * c(a));
+        }
+        k_pos.push(vel);
+        k_vel.push(acc(&pos, &vel));
+    }
+
+    let mut next = state.clone();
+    let mut error = State { pos: Vector2::zeros(), vel: Vector2::zeros() };
+    for ((k_pos, k_vel), (b4, b5)) in k_pos.iter().zip(&k_vel).zip(B4.iter().zip(B5)) {
+        next.pos += *k_pos * (h * c(*b4));
+        next.vel += *k_vel * (h * c(*b4));
+        error.pos += *k_pos * (h * c(b5 - b4));
+        error.vel += *k_vel * (h * c(b5 - b4));
+    }
+    let scale = 1.0 + next.pos.iter().chain(next.vel.iter()).map(|x| x.abs().to_f64().unwrap()).fold(0.0, f64::max);
+    let error = error.pos.iter().chain(error.vel.iter()).map(|x| x.abs().to_f64().unwrap()).fold(0.0, f64::max);
+    (next, error / scale)
+}
+
+/// Cubic Hermite interpolation at the fraction `s` of a step of size `h` from `start` to `end`,
+/// of the position from the velocities and of the velocity from the accelerations `acc`.
+pub(crate) fn hermite<T>(start: &State<T>, end: &State<T>, s: f64, h: T, acc: &dyn Fn(&Vector2<T>, &Vector2<T>) -> Vector2<T>) -> State<T>
+where
+    T: Real + Debug + AddAssign + DivAssign + MulAssign + 'static,
+{
+    let (s2, s3) = (s * s, s * s * s);
+    let h00 = T::from(2.0 * s3 - 3.0 * s2 + 1.0).unwrap();
+    let h10 = T::from(s3 - 2.0 * s2 + s).unwrap() * h;
+    let h01 = T::from(-2.0 * s3 + 3.0 * s2).unwrap();
+    let h11 = T::from(s3 - s2).unwrap() * h;
+    State {
+        pos: start.pos * h00 + start.vel * h10 + end.pos * h01 + end.vel * h11,
+        vel: start.vel * h00 + acc(&start.pos, &start.vel) * h10 + end.vel * h01 + acc(&end.pos, &end.vel) * h11,
+    }
+}
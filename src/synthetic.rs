@@ -0,0 +1,108 @@
+use std::f64::consts::PI;
+
+use super::*;
+
+/// Distribution of the noise added to synthetic positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoiseModel {
+    #[default]
+    Gaussian,
+    Uniform,
+}
+
+impl State<f64> {
+    /// Random state of a bound orbit (for `mu = 1`), at a distance in `r_range` with a speed in
+    /// `speed_range`, both directions being uniformly distributed.
+    ///
+    /// States that would escape are redrawn, so the speed range must start below the escape
+    /// speed `sqrt(2 / r)` at the far end of the distance range.
+    pub fn random_bound<R: Rng + ?Sized>(rng: &mut R, r_range: std::ops::Range<f64>, speed_range: std::ops::Range<f64>) -> Self {
+        assert!(
+            speed_range.start < (2.0 / r_range.end).sqrt(),
+            "no bound orbit has a speed in {:?} at a distance in {:?}", speed_range, r_range,
+        );
+        loop {
+            let r = rng.gen_range(r_range.clone());
+            let speed = rng.gen_range(speed_range.clone());
+            if speed.powi(2) >= 2.0 / r {
+                continue;
+            }
+            return State {
+                pos: bearing_to_unit_vector(rng.gen_range(-PI..PI)) * r,
+                vel: bearing_to_unit_vector(rng.gen_range(-PI..PI)) * speed,
+            };
+        }
+    }
+}
+
+/// Model sample positions of `truth` with Gaussian noise of standard deviation
+/// `pos_noise_sigma` added to each coordinate.
+pub fn synthesize_positions(truth: &State<f64>, pos_noise_sigma: f64, seed: u64, config: &IntegratorConfig) -> Vec<Vector2<f64>> {
+    synthesize_positions_with(truth, NoiseModel::Gaussian, pos_noise_sigma, seed, config)
+}
+
+/// Same as [`synthesize_positions`] with a choice of the noise distribution; `pos_noise_sigma`
+/// is always the standard deviation.
+pub fn synthesize_positions_with(truth: &State<f64>, noise_model: NoiseModel, pos_noise_sigma: f64, seed: u64, config: &IntegratorConfig) -> Vec<Vector2<f64>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut noise = move || match noise_model {
+        NoiseModel::Gaussian => rng.sample::<f64, _>(StandardNormal) * pos_noise_sigma,
+        // a uniform distribution on [-a, a] has standard deviation a / sqrt(3)
+        NoiseModel::Uniform => rng.gen_range(-1.0..=1.0) * pos_noise_sigma * 3.0_f64.sqrt(),
+    };
+    sampled_trajectory(truth, config)
+        .map(|p| p + Vector2::new(noise(), noise()))
+        .collect()
+}
+
+/// Noisy bearing observations of `truth`, see [`synthesize_positions`].
+pub fn synthesize_observations(truth: &State<f64>, pos_noise_sigma: f64, seed: u64, config: &IntegratorConfig) -> Vec<f64> {
+    let relative = synthesize_positions(truth, pos_noise_sigma, seed, config).into_iter()
+        .map(|pos| pos - config.center)
+        .collect::<Vec<_>>();
+    observe(&relative).collect()
+}
+
+/// Empirical spread of the fitted state, see [`monte_carlo_fit`].
+#[derive(Debug, Clone)]
+pub struct MonteCarloResult {
+    /// Mean of the fitted states.
+    pub mean: State<f64>,
+    /// Sample standard deviations of the fitted state components.
+    pub std: StateUncertainty,
+    /// Every fitted state, one per perturbed set of observations.
+    pub samples: Vec<State<f64>>,
+}
+
+/// Monte Carlo uncertainty propagation: refits `n_samples` copies of the bearing `observations`
+/// perturbed by Gaussian noise of standard deviation `sigma`, starting each from the fit of the
+/// unperturbed ones.
+///
+/// Unlike [`fit_trajectory_with_uncertainty`] this captures the nonlinearity of the model. At
+/// least two samples are needed for the standard deviations.
+pub fn monte_carlo_fit(observations: &[f64], sigma: f64, n_samples: usize, seed: u64, config: &FitConfig) -> Result<MonteCarloResult, FitError> {
+    if n_samples < 2 {
+        return Err(FitError::TooFewSamples(n_samples));
+    }
+    let (nominal, _) = fit_trajectory_with_config(observations, config)?;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut samples = Vec::with_capacity(n_samples);
+    for _ in 0..n_samples {
+        let perturbed = observations.iter()
+            .map(|angle| angle + rng.sample::<f64, _>(StandardNormal) * sigma)
+            .collect::<Vec<_>>();
+        let (state, _) = fit_trajectory_with_guess(&perturbed, nominal.clone(), config)?;
+        samples.push(state);
+    }
+
+    let n = samples.len() as f64;
+    let mean = samples.iter().map(Vector4::from).sum::<Vector4<f64>>() / n;
+    let variance = samples.iter()
+        .map(|state| (Vector4::from(state) - mean).map(|d| d * d))
+        .sum::<Vector4<f64>>() / (n - 1.0);
+    Ok(MonteCarloResult {
+        mean: mean.into(),
+        std: StateUncertainty { pos_x: variance[0].sqrt(), pos_y: variance[1].sqrt(), vel_x: variance[2].sqrt(), vel_y: variance[3].sqrt() },
+        samples,
+    })
+}
@@ -0,0 +1,36 @@
+use std::io;
+use std::path::PathBuf;
+
+use orbit_fit_100_loc::*;
+
+/// Writes `contents` to a file in the temporary directory, unique to the test `name`.
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("orbit-fit-{}-{}.csv", name, std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn reads_observations_skipping_comments() {
+    let path = temp_file("valid", "# bearings\n0.1\n\n  0.2 \n-0.3\n");
+    let observations = read_observations_csv(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(observations, vec![0.1, 0.2, -0.3]);
+}
+
+#[test]
+fn rejects_malformed_lines() {
+    let path = temp_file("malformed", "0.1\nnorth\n0.3\n");
+    let error = read_observations_csv(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains(":2:"), "{}", error);
+}
+
+#[test]
+fn rejects_fewer_than_two_observations() {
+    let path = temp_file("short", "# only one\n0.1\n");
+    let error = read_observations_csv(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,186 @@
+use nalgebra::Vector2;
+
+use orbit_fit_100_loc::*;
+
+fn truth() -> State {
+    State {
+        pos: Vector2::new(3.0, -8.0),
+        vel: Vector2::new(0.25, 0.5),
+    }
+}
+
+fn assert_close(actual: &State, expected: &State, pos_tolerance: f64, vel_tolerance: f64) {
+    assert!(
+        (actual.pos - expected.pos).norm() < pos_tolerance && (actual.vel - expected.vel).norm() < vel_tolerance,
+        "expected {:?}, got {:?}",
+        expected,
+        actual,
+    );
+}
+
+#[test]
+fn fit_recovers_noise_free_state() {
+    let config = FitConfig::default();
+    let observations = predicted_observations(&truth(), &config.integration);
+    let (fitted, _) = fit_trajectory(&observations).unwrap();
+    assert_close(&fitted, &truth(), 1e-6, 1e-6);
+}
+
+#[test]
+fn fit_recovers_state_with_small_noise() {
+    let config = FitConfig::default();
+    let observations = synthesize_observations(&truth(), 0.01, 42, &config.integration);
+    let (fitted, _) = fit_trajectory(&observations).unwrap();
+    assert_close(&fitted, &truth(), 0.5, 0.05);
+}
+
+#[test]
+fn fit_rejects_too_few_observations() {
+    assert_eq!(fit_trajectory(&[0.1]).unwrap_err(), FitError::TooFewObservations(1));
+}
+
+#[test]
+fn builder_defaults_match_fit_trajectory() {
+    let observations = synthesize_observations(&truth(), 0.01, 7, &IntegratorConfig::default());
+    let (expected, _) = fit_trajectory(&observations).unwrap();
+    let (state, _) = FitBuilder::new().build().fit(&observations).unwrap();
+    assert_close(&state, &expected, 1e-12, 1e-12);
+}
+
+#[test]
+fn fit_3d_rejects_2d_only_settings() {
+    let mut config = FitConfig::default();
+    config.integration.center = Vector2::new(1.0, 0.0);
+    let observations = vec![(0.1, 0.0); config.integration.num_samples()];
+    assert_eq!(fit_trajectory_3d(&observations, &config).unwrap_err(), FitError::Unsupported("center"));
+    let mut config = FitConfig::default();
+    config.integration.integrator = Integrator::Kepler;
+    assert_eq!(fit_trajectory_3d(&observations, &config).unwrap_err(), FitError::Unsupported("integrator"));
+}
+
+#[test]
+fn weighted_fit_rejects_non_positive_sigmas() {
+    let config = FitConfig::default();
+    let observations = predicted_observations(&truth(), &config.integration);
+    for invalid in [0.0, -1.0, f64::NAN] {
+        let mut sigmas = vec![0.01; observations.len()];
+        sigmas[3] = invalid;
+        assert_eq!(fit_trajectory_weighted(&observations, &sigmas, &Bearing, &config).unwrap_err(), FitError::InvalidSigma(3));
+    }
+}
+
+#[test]
+fn fit_rejects_invalid_solver_settings() {
+    let observations = predicted_observations(&truth(), &FitConfig::default().integration);
+    let config = FitConfig { patience: 0, ..FitConfig::default() };
+    assert_eq!(fit_trajectory_with_config(&observations, &config).unwrap_err(), FitError::InvalidSolverSetting("patience"));
+    let config = FitConfig { initial_step: f64::NAN, ..FitConfig::default() };
+    assert_eq!(fit_trajectory_with_config(&observations, &config).unwrap_err(), FitError::InvalidSolverSetting("initial_step"));
+    let observations_3d = vec![(0.1, 0.0); config.integration.num_samples()];
+    assert_eq!(fit_trajectory_3d(&observations_3d, &config).unwrap_err(), FitError::InvalidSolverSetting("initial_step"));
+}
+
+#[test]
+fn reduced_chi_square_checks_sigmas() {
+    let config = FitConfig::default();
+    let observations = predicted_observations(&truth(), &config.integration);
+    let sigmas = vec![0.01; observations.len()];
+    assert_eq!(reduced_chi_square(&truth(), &observations, &sigmas[1..], &config).unwrap_err(), FitError::SigmaCountMismatch { observations: observations.len(), sigmas: observations.len() - 1 });
+    let mut invalid = sigmas.clone();
+    invalid[2] = 0.0;
+    assert_eq!(reduced_chi_square(&truth(), &observations, &invalid, &config).unwrap_err(), FitError::InvalidSigma(2));
+    assert!(reduced_chi_square(&truth(), &observations, &sigmas, &config).unwrap() < 1e-12);
+}
+
+#[test]
+fn huber_fit_downweights_outlier() {
+    let config = FitConfig::default();
+    let mut observations = synthesize_observations(&truth(), 0.01, 42, &config.integration);
+    observations[7] += 0.5;
+    let (_, _, downweighted) = fit_trajectory_huber(&observations, 0.05, &Bearing, &config).unwrap();
+    assert!(downweighted.contains(&7), "{:?}", downweighted);
+}
+
+#[test]
+fn bounded_fit_stays_within_bounds() {
+    let mut config = FitConfig::default();
+    let observations = predicted_observations(&truth(), &config.integration);
+    config.bounds[0] = (4.0, 10.0);
+    config.bounds[3] = (f64::NEG_INFINITY, 0.4);
+    let (state, _) = fit_trajectory_with_config(&observations, &config).unwrap();
+    assert!(state.pos[0] > 4.0 && state.pos[0] < 10.0, "{:?}", state);
+    assert!(state.vel[1] < 0.4, "{:?}", state);
+}
+
+#[test]
+fn callback_reports_accepted_steps() {
+    let config = FitConfig::default();
+    let observations = synthesize_observations(&truth(), 0.01, 42, &config.integration);
+    let mut evaluations = Vec::new();
+    let (state, report) = fit_trajectory_with_callback(&observations, &config, &mut |state, cost, accepted| {
+        evaluations.push((state.clone(), cost, accepted));
+    }).unwrap();
+    let accepted = evaluations.iter().filter(|(_, _, accepted)| *accepted).collect::<Vec<_>>();
+    assert!(accepted.windows(2).all(|pair| pair[1].1 <= pair[0].1), "accepted costs should not increase");
+    let (last, cost, _) = accepted.last().unwrap();
+    assert_eq!((last.pos, last.vel), (state.pos, state.vel));
+    assert!((cost - report.objective_function).abs() <= 1e-12 * report.objective_function.max(1e-12), "{} vs {}", cost, report.objective_function);
+}
+
+#[test]
+fn two_body_dynamics_match_the_default_force_model() {
+    let config = FitConfig::default();
+    let observations = synthesize_observations(&truth(), 0.01, 7, &config.integration);
+    let (expected, _) = fit_trajectory_with_config(&observations, &config).unwrap();
+    let (state, _) = fit_trajectory_with_dynamics(&observations, &Bearing, &TwoBody::from(&config.integration), &config).unwrap();
+    assert_close(&state, &expected, 1e-9, 1e-9);
+}
+
+#[test]
+fn config_dynamics_use_their_own_force_model() {
+    let config = FitConfig::default();
+    let forces = IntegratorConfig { mu: 2.0, ..IntegratorConfig::default() };
+    let observations = predicted_observations(&truth(), &forces);
+    let (state, _) = fit_trajectory_with_dynamics(&observations, &Bearing, &forces, &config).unwrap();
+    assert_close(&state, &truth(), 1e-6, 1e-6);
+}
+
+#[test]
+fn clipped_fit_rejects_outlier() {
+    let config = FitConfig::default();
+    let mut observations = synthesize_observations(&truth(), 0.01, 42, &config.integration);
+    observations[7] += 0.5;
+    let (_, _, rejected) = fit_trajectory_clipped(&observations, 3.0, 5, &config).unwrap();
+    assert!(rejected.contains(&7), "{:?}", rejected);
+}
+
+#[test]
+fn monte_carlo_needs_two_samples() {
+    let config = FitConfig::default();
+    let observations = predicted_observations(&truth(), &config.integration);
+    assert_eq!(monte_carlo_fit(&observations, 0.01, 1, 0, &config).unwrap_err(), FitError::TooFewSamples(1));
+}
+
+#[test]
+fn covariance_of_fixed_parameters_is_zero() {
+    let mut config = FitConfig::default();
+    let observations = synthesize_observations(&truth(), 0.01, 42, &config.integration);
+    config.free_parameters = [true, true, true, false];
+    let (state, _) = fit_trajectory_with_guess(&observations, truth(), &config).unwrap();
+    let covariance = covariance(&state, &observations, &config).unwrap();
+    for i in 0..4 {
+        assert_eq!((covariance[(3, i)], covariance[(i, 3)]), (0.0, 0.0));
+    }
+    assert!((0..3).all(|i| covariance[(i, i)] > 0.0), "{}", covariance);
+    assert_eq!(config.degrees_of_freedom(observations.len()), Some(observations.len() - 3));
+}
+
+#[test]
+fn conditioning_ignores_fixed_parameters() {
+    let mut config = FitConfig::default();
+    let observations = predicted_observations(&truth(), &config.integration);
+    config.free_parameters = [true, true, true, false];
+    let (state, _, conditioning) = fit_trajectory_with_conditioning(&observations, 1e8, &config).unwrap();
+    let full = condition_number(&jacobian(&state, &observations, &config.integration));
+    assert!(conditioning.condition_number.is_finite() && conditioning.condition_number <= full, "{} > {}", conditioning.condition_number, full);
+}
@@ -0,0 +1,118 @@
+use nalgebra::Vector2;
+
+use orbit_fit_100_loc::*;
+
+#[test]
+fn integrator_runs_with_f32() {
+    let config = IntegratorConfig::default();
+    let initial = State::<f32> {
+        pos: Vector2::new(3.0, -8.0),
+        vel: Vector2::new(0.25, 0.5),
+    };
+    let trajectory = integrate_trajectory_euler::<f32>(&initial, &config).collect::<Vec<_>>();
+    assert_eq!(trajectory.len(), config.steps);
+    for state in &trajectory {
+        assert!(state.pos.iter().chain(state.vel.iter()).all(|x| x.is_finite()));
+        assert!(state.pos.norm() < 20.0, "trajectory escaped: {:?}", state);
+    }
+
+    let sampled = trajectory.iter().step_by(config.stride).map(|s| s.pos).collect::<Vec<_>>();
+    let bearings = observe::<f32>(&sampled).collect::<Vec<_>>();
+    let reference = observe(&sampled_trajectory(&State { pos: Vector2::new(3.0, -8.0), vel: Vector2::new(0.25, 0.5) }, &config).collect::<Vec<_>>()).collect::<Vec<f64>>();
+    for (bearing, reference) in bearings.iter().zip(&reference) {
+        assert!((*bearing as f64 - reference).abs() < 1e-3, "{} vs {}", bearing, reference);
+    }
+}
+
+#[test]
+fn euler_integrates_backward() {
+    let forward = IntegratorConfig { dt: 0.01, steps: 1000, ..IntegratorConfig::default() };
+    let backward = IntegratorConfig { dt: -0.01, ..forward.clone() };
+    let initial = State {
+        pos: Vector2::new(3.0, -8.0),
+        vel: Vector2::new(0.25, 0.5),
+    };
+    let end = integrate_trajectory_euler(&initial, &forward).last().unwrap();
+    assert!((end.pos - initial.pos).norm() > 1.0, "the test trajectory should move: {:?}", end);
+
+    let trajectory = integrate_trajectory_euler(&end, &backward).collect::<Vec<_>>();
+    assert_eq!(trajectory.len(), backward.steps);
+    let start = trajectory.last().unwrap();
+    assert!((start.pos - initial.pos).norm() < 1e-2, "{:?}", start);
+    assert!((start.vel - initial.vel).norm() < 1e-3, "{:?}", start);
+}
+
+#[test]
+fn symplectic_euler_drifts_less_than_euler() {
+    let euler = IntegratorConfig { dt: 0.05, steps: 2000, ..IntegratorConfig::default() };
+    let symplectic = IntegratorConfig { integrator: Integrator::SymplecticEuler, ..euler.clone() };
+    let initial = State {
+        pos: Vector2::new(1.0, 0.0),
+        vel: Vector2::new(0.0, 0.8),
+    };
+    let euler_drift = energy_drift(&initial, &euler);
+    let symplectic_drift = energy_drift(&initial, &symplectic);
+    assert!(euler_drift.abs() > 0.5, "{}", euler_drift);
+    assert!(symplectic_drift.abs() < 0.05, "{}", symplectic_drift);
+}
+
+#[test]
+fn kepler_propagation_conserves_eccentricity_vector() {
+    let config = IntegratorConfig { integrator: Integrator::Kepler, dt: 0.1, steps: 200, ..IntegratorConfig::default() };
+    let initial = State {
+        pos: Vector2::new(1.0, 0.5),
+        vel: Vector2::new(-0.3, 0.8),
+    };
+    let expected = eccentricity_vector(&initial, config.mu);
+    for state in integrate_trajectory(&initial, &config) {
+        let e = eccentricity_vector(&state, config.mu);
+        assert!((e - expected).norm() < 1e-9, "{} vs {}", e, expected);
+    }
+}
+
+#[test]
+fn kepler_propagation_matches_fine_rk4() {
+    let kepler = IntegratorConfig { integrator: Integrator::Kepler, dt: 0.5, steps: 40, ..IntegratorConfig::default() };
+    let rk4 = IntegratorConfig { integrator: Integrator::Rk4, dt: 0.005, steps: 4000, ..IntegratorConfig::default() };
+    let initial = State {
+        pos: Vector2::new(1.0, 0.5),
+        vel: Vector2::new(-0.3, 0.8),
+    };
+    let fine = integrate_trajectory(&initial, &rk4).collect::<Vec<_>>();
+    for (i, state) in integrate_trajectory(&initial, &kepler).enumerate() {
+        let reference = &fine[(i + 1) * 100 - 1];
+        assert!((state.pos - reference.pos).norm() < 1e-6, "step {}: {:?} vs {:?}", i, state, reference);
+        assert!((state.vel - reference.vel).norm() < 1e-6, "step {}: {:?} vs {:?}", i, state, reference);
+    }
+}
+
+#[test]
+fn rkf45_matches_kepler_propagation() {
+    let rkf45 = IntegratorConfig { integrator: Integrator::Rkf45, dt: 0.5, steps: 40, tolerance: 1e-10, ..IntegratorConfig::default() };
+    let kepler = IntegratorConfig { integrator: Integrator::Kepler, ..rkf45.clone() };
+    let initial = State {
+        pos: Vector2::new(1.0, 0.5),
+        vel: Vector2::new(-0.3, 0.8),
+    };
+    for (i, (state, reference)) in integrate_trajectory(&initial, &rkf45).zip(integrate_trajectory(&initial, &kepler)).enumerate() {
+        assert!((state.pos - reference.pos).norm() < 1e-5, "step {}: {:?} vs {:?}", i, state, reference);
+        assert!((state.vel - reference.vel).norm() < 1e-5, "step {}: {:?} vs {:?}", i, state, reference);
+    }
+}
+
+#[test]
+fn states_at_handles_times_before_the_epoch() {
+    let config = IntegratorConfig { integrator: Integrator::Rk4, dt: 0.01, ..IntegratorConfig::default() };
+    let kepler = IntegratorConfig { integrator: Integrator::Kepler, ..config.clone() };
+    let initial = State {
+        pos: Vector2::new(1.0, 0.5),
+        vel: Vector2::new(-0.3, 0.8),
+    };
+    let times = [1.5, -2.25, 0.0, -0.5, 3.0];
+    let states = states_at(&initial, &times, &config);
+    let expected = states_at(&initial, &times, &kepler);
+    for ((state, expected), t) in states.iter().zip(&expected).zip(times) {
+        assert!((state.pos - expected.pos).norm() < 1e-6, "t = {}: {:?} vs {:?}", t, state, expected);
+        assert!((state.vel - expected.vel).norm() < 1e-6, "t = {}: {:?} vs {:?}", t, state, expected);
+    }
+}
@@ -0,0 +1,53 @@
+use levenberg_marquardt::LeastSquaresProblem;
+use nalgebra::Vector2;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use orbit_fit_100_loc::*;
+
+#[test]
+fn jacobian_matches_finite_differences() {
+    let config = IntegratorConfig::default();
+    let truth = State {
+        pos: Vector2::new(3.0, -8.0),
+        vel: Vector2::new(0.25, 0.5),
+    };
+    let observations = synthesize_observations(&truth, 0.1, 1, &config);
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..5 {
+        let angle = rng.gen_range(-3.0..3.0);
+        let radius = rng.gen_range(3.0..8.0);
+        let speed = rng.gen_range(0.2..0.5);
+        let state = State {
+            pos: Vector2::new(angle.cos(), angle.sin()) * radius,
+            vel: Vector2::new(-angle.sin(), angle.cos()) * speed,
+        };
+        let analytic = jacobian(&state, &observations, &config);
+        let numeric = finite_difference_jacobian(&state, &observations, &config, 1e-6);
+        for (a, n) in analytic.iter().zip(numeric.iter()) {
+            assert!((a - n).abs() <= 1e-6 * (1.0 + a.abs()), "autodiff {} vs finite differences {} for {:?}", a, n, state);
+        }
+    }
+}
+
+#[test]
+fn kepler_stm_matches_autodiff_of_kepler_propagation() {
+    let config = IntegratorConfig { integrator: Integrator::Kepler, ..IntegratorConfig::default() };
+    let truth = State {
+        pos: Vector2::new(3.0, -8.0),
+        vel: Vector2::new(0.2, 0.25),
+    };
+    let observations = synthesize_observations(&truth, 0.01, 1, &config);
+    let state = State {
+        pos: Vector2::new(3.5, -7.5),
+        vel: Vector2::new(0.15, 0.3),
+    };
+    let autodiff = OptimizationProblem::new(state.clone(), &observations, &Bearing, &config);
+    let stm = OptimizationProblem::new(state, &observations, &Bearing, &config).with_jacobian_mode(JacobianMode::KeplerStm);
+    let expected = LeastSquaresProblem::jacobian(&autodiff).unwrap();
+    let actual = LeastSquaresProblem::jacobian(&stm).unwrap();
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        assert!((a - e).abs() <= 1e-9 * (1.0 + e.abs()), "Kepler STM {} vs autodiff {}", a, e);
+    }
+    let (residuals, expected) = (LeastSquaresProblem::residuals(&stm).unwrap(), LeastSquaresProblem::residuals(&autodiff).unwrap());
+    assert!((residuals - expected).norm() < 1e-12, "{} vs {}", residuals, expected);
+}
@@ -0,0 +1,41 @@
+#![cfg(feature = "serde")]
+
+use nalgebra::Vector2;
+
+use orbit_fit_100_loc::*;
+
+#[test]
+fn state_round_trips_through_json() {
+    let state = State {
+        pos: Vector2::new(3.0, -8.0),
+        vel: Vector2::new(0.25, 0.5),
+    };
+    let json = serde_json::to_string(&state).unwrap();
+    assert_eq!(json, r#"{"pos":[3.0,-8.0],"vel":[0.25,0.5]}"#);
+    let back: State = serde_json::from_str(&json).unwrap();
+    assert_eq!((back.pos, back.vel), (state.pos, state.vel));
+}
+
+#[test]
+fn fit_record_round_trips_through_json() {
+    let config = FitConfig::default();
+    let observations = predicted_observations(&State { pos: Vector2::new(3.0, -8.0), vel: Vector2::new(0.25, 0.5) }, &config.integration);
+    let record = FitRecord::new(&observations, &config).unwrap();
+    let json = serde_json::to_string(&record).unwrap();
+    let back: FitRecord = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.config.bounds, record.config.bounds);
+    // `serde_json` may parse floats one ulp off
+    let close = |a: f64, b: f64| (a - b).abs() <= 1e-15 * a.abs().max(1.0);
+    assert!(back.observations.iter().zip(&record.observations).all(|(&a, &b)| close(a, b)));
+    assert!((back.state.pos - record.state.pos).norm() < 1e-12 && (back.state.vel - record.state.vel).norm() < 1e-12);
+    assert_eq!(back.covariance.is_some(), record.covariance.is_some());
+}
+
+#[test]
+fn infinite_bounds_round_trip_through_json() {
+    let mut config = FitConfig::default();
+    config.bounds[0] = (0.0, f64::INFINITY);
+    config.bounds[3] = (f64::NEG_INFINITY, 1.0);
+    let back: FitConfig = serde_json::from_str(&serde_json::to_string(&config).unwrap()).unwrap();
+    assert_eq!(back.bounds, config.bounds);
+}
@@ -0,0 +1,59 @@
+use nalgebra::{Vector2, Vector4};
+
+use orbit_fit_100_loc::*;
+
+#[test]
+fn vector4_conversion_keeps_parameter_order() {
+    let state = State {
+        pos: Vector2::new(1.0, 2.0),
+        vel: Vector2::new(3.0, 4.0),
+    };
+    let params = Vector4::from(&state);
+    assert_eq!(params, Vector4::new(1.0, 2.0, 3.0, 4.0));
+    let back = State::from(params);
+    assert_eq!((back.pos, back.vel), (state.pos, state.vel));
+}
+
+#[test]
+fn bearing_helpers_are_inverse() {
+    for angle in [-3.0, -1.0, 0.0, 0.5, 2.5] {
+        let v = bearing_to_unit_vector(angle);
+        assert!((v.norm() - 1.0).abs() < 1e-12);
+        assert!((bearing_of(&(v * 3.0)) - angle).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn parses_state_vectors() {
+    let state = State::from_state_vector_str(" 3 -8\t0.25 0.5\n").unwrap();
+    assert_eq!((state.pos, state.vel), (Vector2::new(3.0, -8.0), Vector2::new(0.25, 0.5)));
+    assert_eq!(State::from_state_vector_str("1 2 3").unwrap_err(), ParseStateError::FieldCount(3));
+    assert!(matches!(State::from_state_vector_str("1 2 x 4"), Err(ParseStateError::InvalidNumber { index: 2, .. })));
+    assert_eq!(State::from_state_vector_str("1 2 inf 4").unwrap_err(), ParseStateError::NonFinite(2));
+}
+
+#[test]
+fn keplerian_elements_round_trip() {
+    let mu = 1.5;
+    for (pos, vel) in [
+        (Vector2::new(3.0, -8.0), Vector2::new(0.25, 0.5)),
+        (Vector2::new(1.0, 0.5), Vector2::new(-0.3, 0.8)),
+        (Vector2::new(-2.0, 1.0), Vector2::new(0.4, 0.6)),
+        (Vector2::new(0.5, 0.5), Vector2::new(1.5, -1.0)),
+    ] {
+        let state = State { pos, vel };
+        let back = KeplerianElements::from_state(&state, mu).to_state(mu);
+        assert!((back.pos - state.pos).norm() < 1e-9 && (back.vel - state.vel).norm() < 1e-9, "{:?} vs {:?}", back, state);
+    }
+}
+
+#[test]
+fn display_is_relative_to_the_central_body() {
+    let state = State {
+        pos: Vector2::new(6.0, 0.0),
+        vel: Vector2::new(0.0, 1.0),
+    };
+    let config = IntegratorConfig { center: Vector2::new(5.0, 0.0), ..IntegratorConfig::default() };
+    let text = state.display(&config).to_string();
+    assert!(text.contains("|r|=1.00") && text.contains("E=-0.5000"), "{}", text);
+}